@@ -1,10 +1,11 @@
 use crate::{
     editor::EditorCommand,
-    ig::{Ig, SearchConfig, SortKey},
+    history::{History, RecalledSearch, SearchFlags},
+    ig::{Ig, PatternError, SearchConfig, SortKey},
     ui::{
-        bottom_bar, context_viewer::ContextViewer, input_handler::InputHandler,
-        keymap_popup::KeymapPopup, result_list::ResultList, search_popup::SearchPopup,
-        theme::Theme,
+        bottom_bar, context_viewer::ContextViewer, errors_popup::ErrorsPopup,
+        input_handler::InputHandler, keymap_popup::KeymapPopup, result_list::ResultList,
+        search_popup::SearchPopup, stats_popup::StatsPopup, theme::Theme,
     },
 };
 use anyhow::Result;
@@ -13,12 +14,13 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use grep::regex::RegexMatcherBuilder;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
     Frame, Terminal,
 };
-use std::path::PathBuf;
+use std::fs::File;
 
 pub struct App {
     search_config: SearchConfig,
@@ -27,7 +29,15 @@ pub struct App {
     result_list: ResultList,
     context_viewer: ContextViewer,
     search_popup: SearchPopup,
+    refine_popup: SearchPopup,
+    refine_keep: bool,
+    fuzzy_popup: SearchPopup,
     keymap_popup: KeymapPopup,
+    stats_popup: StatsPopup,
+    errors_popup: ErrorsPopup,
+    history: History,
+    /// Flags of the search recalled from history, applied to searches from the search popup.
+    recalled_flags: Option<SearchFlags>,
 }
 
 impl App {
@@ -35,24 +45,45 @@ impl App {
         search_config: SearchConfig,
         editor_command: EditorCommand,
         context_viewer: ContextViewer,
+        search_popup: SearchPopup,
+        history: History,
         theme: Box<dyn Theme>,
+        error_log: Option<File>,
     ) -> Self {
         let theme = theme;
         Self {
             search_config,
-            ig: Ig::new(editor_command),
+            ig: Ig::new(editor_command, error_log),
             theme,
             context_viewer,
             result_list: ResultList::default(),
-            search_popup: SearchPopup::default(),
+            search_popup,
+            refine_popup: SearchPopup::new(false),
+            refine_keep: true,
+            fuzzy_popup: SearchPopup::new(true),
             keymap_popup: KeymapPopup::default(),
+            stats_popup: StatsPopup::default(),
+            errors_popup: ErrorsPopup::default(),
+            history,
+            recalled_flags: None,
         }
     }
 
     pub fn run(&mut self) -> Result<()> {
         let mut input_handler = InputHandler::default();
-        self.ig
-            .search(self.search_config.clone(), &mut self.result_list);
+        if self
+            .ig
+            .search(self.search_config.clone(), &mut self.result_list)
+        {
+            self.history.add(&self.search_config);
+        } else if let Some(error) = self.ig.pattern_error().cloned() {
+            // pattern given on the command line is fixed in the search popup
+            self.search_popup
+                .set_patterns(self.search_config.patterns.clone());
+            self.search_popup.toggle();
+            self.search_popup.set_error(Some(error));
+            input_handler = InputHandler::text_insertion();
+        }
 
         loop {
             let backend = CrosstermBackend::new(std::io::stdout());
@@ -79,14 +110,35 @@ impl App {
 
                 input_handler.handle_input(self)?;
 
+                // an empty pattern matches every line, it is searched only when confirmed
+                if let Some(patterns) = self.search_popup.take_debounced_patterns() {
+                    if patterns.iter().any(|pattern| !pattern.is_empty()) {
+                        self.search_patterns(patterns);
+                    }
+                }
+
+                if let Some(terms) = self.fuzzy_popup.take_debounced_patterns() {
+                    self.result_list.fuzzy_filter(&terms.join(" "));
+                }
+
                 if let Some((file_name, _)) = self.result_list.get_selected_entry() {
-                    self.context_viewer
-                        .update_if_needed(PathBuf::from(file_name), self.theme.as_ref());
+                    self.context_viewer.update_if_needed(
+                        self.search_config.resolve_path(&file_name),
+                        &self.search_config,
+                        self.theme.as_ref(),
+                    );
                 }
             }
 
-            self.ig
-                .open_file_if_requested(self.result_list.get_selected_entry());
+            // captured standard input is opened from its temporary copy
+            let selected_entry =
+                self.result_list
+                    .get_selected_entry()
+                    .map(|(file_name, line_number)| {
+                        let path = self.search_config.resolve_path(&file_name);
+                        (path.to_string_lossy().into_owned(), line_number)
+                    });
+            self.ig.open_file_if_requested(selected_entry);
 
             if self.ig.exit_requested() {
                 execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
@@ -98,6 +150,107 @@ impl App {
         Ok(())
     }
 
+    /// Returns `false` if the patterns do not compile, the error is shown in the search popup then.
+    fn search_patterns(&mut self, patterns: Vec<String>) -> bool {
+        let mut search_config = self.search_config.clone();
+        search_config.patterns = patterns;
+        if let Some(flags) = self.recalled_flags {
+            flags.apply(&mut search_config);
+        }
+        if self.ig.search(search_config.clone(), &mut self.result_list) {
+            self.search_config = search_config;
+        }
+        let error = self.ig.pattern_error().cloned();
+        let valid = error.is_none();
+        self.search_popup.set_error(error);
+        valid
+    }
+
+    /// Sorts by `key`, or by `reversed` if results are already sorted by `key`.
+    /// Results are sorted in place, unless the key is applied while walking directories.
+    fn toggle_sort(&mut self, key: SortKey, reversed: SortKey) {
+        let sort_by = match self.search_config.sort_by {
+            Some(current) if current.name() == key.name() && !current.is_reversed() => reversed,
+            _ => key,
+        };
+        self.search_config.sort_by = Some(sort_by);
+        if sort_by.is_applied_to_results() {
+            self.result_list.sort(sort_by);
+        } else {
+            self.ig
+                .search(self.search_config.clone(), &mut self.result_list);
+        }
+    }
+
+    /// Replaces patterns in the search popup and flags they are searched with,
+    /// history is not recalled in refine and fuzzy popups.
+    fn recall_history(
+        &mut self,
+        recall: impl FnOnce(&mut History, RecalledSearch) -> Option<RecalledSearch>,
+    ) {
+        if self.refine_popup.is_visible() || self.fuzzy_popup.is_visible() {
+            return;
+        }
+        let current = RecalledSearch {
+            patterns: self.search_popup.get_patterns(),
+            flags: self
+                .recalled_flags
+                .unwrap_or_else(|| SearchFlags::new(&self.search_config)),
+        };
+        if let Some(recalled) = recall(&mut self.history, current) {
+            self.recalled_flags = Some(recalled.flags);
+            self.search_popup.recall_patterns(recalled.patterns);
+        }
+    }
+
+    /// Popup receiving text input, refine and fuzzy popups are open on top of the search one.
+    fn active_popup(&mut self) -> &mut SearchPopup {
+        if self.refine_popup.is_visible() {
+            &mut self.refine_popup
+        } else if self.fuzzy_popup.is_visible() {
+            &mut self.fuzzy_popup
+        } else {
+            &mut self.search_popup
+        }
+    }
+
+    fn open_refine_popup(&mut self, keep: bool) {
+        self.refine_keep = keep;
+        self.refine_popup.set_title(if keep {
+            " Keep Lines Matching "
+        } else {
+            " Discard Lines Matching "
+        });
+        self.refine_popup.set_patterns(Vec::new());
+        self.refine_popup.toggle();
+    }
+
+    /// Returns `false` if the patterns do not compile, the error is shown in the refine popup then.
+    fn refine(&mut self) -> bool {
+        let patterns = self.refine_popup.get_patterns();
+        if patterns.iter().all(String::is_empty) {
+            return true;
+        }
+
+        let build = |patterns: &[String]| {
+            RegexMatcherBuilder::new()
+                .case_insensitive(self.search_config.case_insensitive)
+                .case_smart(self.search_config.case_smart)
+                .build_many(patterns)
+        };
+        match build(&patterns) {
+            Ok(matcher) => {
+                self.result_list.refine(matcher, self.refine_keep);
+                true
+            }
+            Err(_) => {
+                self.refine_popup
+                    .set_error(PatternError::find(&patterns, build));
+                false
+            }
+        }
+    }
+
     fn draw(frame: &mut Frame, app: &mut App, input_handler: &InputHandler) {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -107,7 +260,8 @@ impl App {
         let (view_area, bottom_bar_area) = (chunks[0], chunks[1]);
         let (list_area, context_viewer_area) = app.context_viewer.split_view(view_area);
 
-        app.result_list.draw(frame, list_area, app.theme.as_ref());
+        app.result_list
+            .draw(frame, list_area, &app.search_config, app.theme.as_ref());
 
         if let Some(cv_area) = context_viewer_area {
             app.context_viewer
@@ -119,11 +273,18 @@ impl App {
             bottom_bar_area,
             &app.result_list,
             &app.ig,
+            &app.search_config,
             input_handler,
             app.theme.as_ref(),
         );
 
         app.search_popup.draw(frame, app.theme.as_ref());
+        app.refine_popup.draw(frame, app.theme.as_ref());
+        app.fuzzy_popup.draw(frame, app.theme.as_ref());
+        app.stats_popup
+            .draw(frame, &app.ig, &app.result_list, app.theme.as_ref());
+        app.errors_popup
+            .draw(frame, app.ig.errors(), app.theme.as_ref());
         app.keymap_popup.draw(frame, app.theme.as_ref());
     }
 }
@@ -182,41 +343,64 @@ impl Application for App {
     }
 
     fn on_toggle_sort_name(&mut self) {
-        match self.search_config.sort_by {
-            Some(SortKey::Path) => self.search_config.sort_by = Some(SortKey::PathReversed),
-            Some(_) => self.search_config.sort_by = Some(SortKey::Path),
-            None => self.search_config.sort_by = Some(SortKey::Path),
-        }
+        self.toggle_sort(SortKey::Path, SortKey::PathReversed);
+    }
+
+    fn on_toggle_sort_mtime(&mut self) {
+        self.toggle_sort(SortKey::Modified, SortKey::ModifiedReversed);
+    }
+
+    fn on_toggle_sort_ctime(&mut self) {
+        self.toggle_sort(SortKey::Created, SortKey::CreatedReversed);
+    }
+
+    fn on_toggle_sort_atime(&mut self) {
+        self.toggle_sort(SortKey::Accessed, SortKey::AccessedReversed);
+    }
+
+    fn on_toggle_sort_matches(&mut self) {
+        self.toggle_sort(SortKey::Matches, SortKey::MatchesReversed);
+    }
+
+    fn on_toggle_sort_size(&mut self) {
+        self.toggle_sort(SortKey::Size, SortKey::SizeReversed);
+    }
+
+    fn on_toggle_sort_depth(&mut self) {
+        self.toggle_sort(SortKey::Depth, SortKey::DepthReversed);
+    }
+
+    fn on_toggle_sort_natural(&mut self) {
+        self.toggle_sort(SortKey::Natural, SortKey::NaturalReversed);
+    }
+
+    fn on_toggle_no_ignore(&mut self) {
+        self.search_config.toggle_no_ignore();
         self.ig
             .search(self.search_config.clone(), &mut self.result_list);
     }
 
-    fn on_toggle_sort_mtime(&mut self) {
-        match self.search_config.sort_by {
-            Some(SortKey::Modified) => self.search_config.sort_by = Some(SortKey::ModifiedReversed),
-            Some(_) => self.search_config.sort_by = Some(SortKey::Modified),
-            None => self.search_config.sort_by = Some(SortKey::Modified),
-        }
+    fn on_toggle_no_ignore_vcs(&mut self) {
+        self.search_config.no_ignore_vcs = !self.search_config.no_ignore_vcs;
         self.ig
             .search(self.search_config.clone(), &mut self.result_list);
     }
 
-    fn on_toggle_sort_ctime(&mut self) {
-        match self.search_config.sort_by {
-            Some(SortKey::Created) => self.search_config.sort_by = Some(SortKey::CreatedReversed),
-            Some(_) => self.search_config.sort_by = Some(SortKey::Created),
-            None => self.search_config.sort_by = Some(SortKey::Created),
-        }
+    fn on_toggle_no_ignore_parent(&mut self) {
+        self.search_config.no_ignore_parent = !self.search_config.no_ignore_parent;
         self.ig
             .search(self.search_config.clone(), &mut self.result_list);
     }
 
-    fn on_toggle_sort_atime(&mut self) {
-        match self.search_config.sort_by {
-            Some(SortKey::Modified) => self.search_config.sort_by = Some(SortKey::ModifiedReversed),
-            Some(_) => self.search_config.sort_by = Some(SortKey::Modified),
-            None => self.search_config.sort_by = Some(SortKey::Modified),
-        }
+    fn on_toggle_no_ignore_files(&mut self) {
+        self.search_config.no_ignore_files = !self.search_config.no_ignore_files;
+        self.ig
+            .search(self.search_config.clone(), &mut self.result_list);
+    }
+
+    fn on_cycle_unrestricted(&mut self) {
+        self.search_config
+            .set_unrestricted_level((self.search_config.unrestricted + 1) % 4);
         self.ig
             .search(self.search_config.clone(), &mut self.result_list);
     }
@@ -225,11 +409,23 @@ impl Application for App {
         self.ig.open_file();
     }
 
-    fn on_search(&mut self) {
-        let pattern = self.search_popup.get_pattern();
-        self.search_config.pattern = pattern;
-        self.ig
-            .search(self.search_config.clone(), &mut self.result_list);
+    fn on_search(&mut self) -> bool {
+        if self.refine_popup.is_visible() {
+            self.refine()
+        } else if self.fuzzy_popup.is_visible() {
+            if let Some(terms) = self.fuzzy_popup.take_pending_patterns() {
+                self.result_list.fuzzy_filter(&terms.join(" "));
+            }
+            true
+        } else {
+            // confirming searches again, also the edit which is still debounced
+            self.search_popup.take_pending_patterns();
+            let searched = self.search_patterns(self.search_popup.get_patterns());
+            if searched {
+                self.history.add(&self.search_config);
+            }
+            searched
+        }
     }
 
     fn on_exit(&mut self) {
@@ -237,29 +433,87 @@ impl Application for App {
     }
 
     fn on_toggle_popup(&mut self) {
+        if self.refine_popup.is_visible() {
+            self.refine_popup.toggle();
+            return;
+        }
+        if self.fuzzy_popup.is_visible() {
+            // the filter follows the popup, also the edit which is still debounced
+            if let Some(terms) = self.fuzzy_popup.take_pending_patterns() {
+                self.result_list.fuzzy_filter(&terms.join(" "));
+            }
+            self.fuzzy_popup.toggle();
+            return;
+        }
+
         self.search_popup
-            .set_pattern(self.search_config.pattern.clone());
+            .set_patterns(self.search_config.patterns.clone());
         self.search_popup.toggle();
+        self.history.reset();
+        self.recalled_flags = None;
+    }
+
+    fn on_refine_keep(&mut self) {
+        self.open_refine_popup(true);
+    }
+
+    fn on_refine_discard(&mut self) {
+        self.open_refine_popup(false);
+    }
+
+    fn on_undo_refinement(&mut self) {
+        self.result_list.undo_refinement();
+    }
+
+    fn on_fuzzy_filter(&mut self) {
+        let query = self.result_list.get_fuzzy_query().unwrap_or_default();
+        self.fuzzy_popup.set_title(" Fuzzy Filter ");
+        self.fuzzy_popup.set_patterns(vec![query.into()]);
+        self.fuzzy_popup.toggle();
+    }
+
+    fn on_continue_search(&mut self) {
+        self.ig.continue_search();
     }
 
     fn on_char_inserted(&mut self, c: char) {
-        self.search_popup.insert_char(c);
+        self.active_popup().insert_char(c);
     }
 
     fn on_char_removed(&mut self) {
-        self.search_popup.remove_char();
+        self.active_popup().remove_char();
     }
 
     fn on_char_deleted(&mut self) {
-        self.search_popup.delete_char();
+        self.active_popup().delete_char();
     }
 
     fn on_char_left(&mut self) {
-        self.search_popup.move_cursor_left();
+        self.active_popup().move_cursor_left();
     }
 
     fn on_char_right(&mut self) {
-        self.search_popup.move_cursor_right();
+        self.active_popup().move_cursor_right();
+    }
+
+    fn on_next_pattern(&mut self) {
+        self.active_popup().next_pattern();
+    }
+
+    fn on_previous_pattern(&mut self) {
+        self.active_popup().previous_pattern();
+    }
+
+    fn on_history_previous(&mut self) {
+        self.recall_history(History::older);
+    }
+
+    fn on_history_next(&mut self) {
+        self.recall_history(|history, _| history.newer());
+    }
+
+    fn on_history_search(&mut self) {
+        self.recall_history(History::search);
     }
 
     fn on_toggle_keymap(&mut self) {
@@ -281,6 +535,22 @@ impl Application for App {
     fn on_keymap_right(&mut self) {
         self.keymap_popup.go_right();
     }
+
+    fn on_toggle_stats(&mut self) {
+        self.stats_popup.toggle();
+    }
+
+    fn on_toggle_errors(&mut self) {
+        self.errors_popup.toggle();
+    }
+
+    fn on_errors_up(&mut self) {
+        self.errors_popup.go_up();
+    }
+
+    fn on_errors_down(&mut self) {
+        self.errors_popup.go_down();
+    }
 }
 
 #[cfg_attr(test, mockall::automock)]
@@ -302,18 +572,42 @@ pub trait Application {
     fn on_toggle_sort_mtime(&mut self);
     fn on_toggle_sort_ctime(&mut self);
     fn on_toggle_sort_atime(&mut self);
+    fn on_toggle_sort_matches(&mut self);
+    fn on_toggle_sort_size(&mut self);
+    fn on_toggle_sort_depth(&mut self);
+    fn on_toggle_sort_natural(&mut self);
+    fn on_toggle_no_ignore(&mut self);
+    fn on_toggle_no_ignore_vcs(&mut self);
+    fn on_toggle_no_ignore_parent(&mut self);
+    fn on_toggle_no_ignore_files(&mut self);
+    fn on_cycle_unrestricted(&mut self);
     fn on_open_file(&mut self);
-    fn on_search(&mut self);
+    /// Returns `false` if the popup should stay open, e.g. because the pattern does not compile.
+    fn on_search(&mut self) -> bool;
     fn on_exit(&mut self);
     fn on_toggle_popup(&mut self);
+    fn on_refine_keep(&mut self);
+    fn on_refine_discard(&mut self);
+    fn on_undo_refinement(&mut self);
+    fn on_fuzzy_filter(&mut self);
+    fn on_continue_search(&mut self);
     fn on_char_inserted(&mut self, c: char);
     fn on_char_removed(&mut self);
     fn on_char_deleted(&mut self);
     fn on_char_left(&mut self);
     fn on_char_right(&mut self);
+    fn on_next_pattern(&mut self);
+    fn on_previous_pattern(&mut self);
+    fn on_history_previous(&mut self);
+    fn on_history_next(&mut self);
+    fn on_history_search(&mut self);
     fn on_toggle_keymap(&mut self);
     fn on_keymap_up(&mut self);
     fn on_keymap_down(&mut self);
     fn on_keymap_left(&mut self);
     fn on_keymap_right(&mut self);
+    fn on_toggle_stats(&mut self);
+    fn on_toggle_errors(&mut self);
+    fn on_errors_up(&mut self);
+    fn on_errors_down(&mut self);
 }
@@ -1,8 +1,10 @@
 use crate::{
     editor::Editor,
+    ig::Engine,
     ui::{context_viewer::ContextViewerPosition, theme::ThemeVariant},
 };
-use clap::{CommandFactory, Parser, ValueEnum};
+use anyhow::{Context, Result};
+use clap::{ArgGroup, CommandFactory, Parser, ValueEnum};
 use std::{
     ffi::OsString,
     fs::File,
@@ -19,13 +21,37 @@ pub const VISUAL_ENV: &str = "VISUAL";
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[command(group(ArgGroup::new("pattern_or_type").required(true).multiple(true)))]
 pub struct Args {
     /// Regular expression used for searching.
-    #[arg(group = "pattern_or_type", required = true)]
+    /// Treated as a path if patterns are provided with --regexp or --file.
+    #[arg(group = "pattern_or_type")]
     pub pattern: Option<String>,
+    /// Regular expression used for searching. Multiple patterns may be provided,
+    /// lines matching any of them are shown.
+    #[clap(
+        short = 'e',
+        long = "regexp",
+        value_name = "PATTERN",
+        group = "pattern_or_type"
+    )]
+    pub regexps: Vec<String>,
+    /// Search for patterns from the given file, one pattern per line.
+    /// Empty lines are skipped. Multiple files may be provided.
+    #[clap(
+        short = 'f',
+        long = "file",
+        value_name = "PATTERNFILE",
+        group = "pattern_or_type"
+    )]
+    pub pattern_files: Vec<PathBuf>,
     /// Files or directories to search. Directories are searched recursively.
     /// If not specified, searching starts from current directory.
     pub paths: Vec<PathBuf>,
+    /// Search only files listed in FILE, separated by NUL characters or newlines.
+    /// Use - to read the list from standard input. PATHS are not searched then.
+    #[clap(long, value_name = "FILE")]
+    pub files_from: Option<PathBuf>,
     #[clap(flatten)]
     pub editor: EditorOpt,
     /// UI color theme.
@@ -42,6 +68,27 @@ pub struct Args {
     /// By default, hidden files and directories are skipped.
     #[clap(short = '.', long = "hidden")]
     pub search_hidden: bool,
+    /// Do not respect ignore files (.gitignore, .ignore, .igrepignore, etc.).
+    /// Files given with --ignore-file are still respected.
+    #[clap(long)]
+    pub no_ignore: bool,
+    /// Do not respect version control ignore files (.gitignore, .git/info/exclude, etc.).
+    #[clap(long)]
+    pub no_ignore_vcs: bool,
+    /// Do not respect ignore files in parent directories.
+    #[clap(long)]
+    pub no_ignore_parent: bool,
+    /// Do not respect files given with --ignore-file.
+    #[clap(long)]
+    pub no_ignore_files: bool,
+    /// Ignore files and directories matching gitignore rules from the given file.
+    /// Multiple files may be provided.
+    #[clap(long, value_name = "PATH")]
+    pub ignore_file: Vec<PathBuf>,
+    /// Reduce the level of smart filtering. Repeated up to three times:
+    /// -u is --no-ignore, -uu also searches hidden files, -uuu also searches binary files.
+    #[clap(short = 'u', long, action = clap::ArgAction::Count)]
+    pub unrestricted: u8,
     /// Follow symbolic links while traversing directories.
     #[clap(short = 'L', long = "follow")]
     pub follow_links: bool,
@@ -51,15 +98,63 @@ pub struct Args {
     /// Exact matches with no regex. Useful when searching for a string full of delimiters.
     #[clap(short = 'F', long = "fixed-strings")]
     pub fixed_strings: bool,
+    /// Use PCRE2 regex engine, which supports look-around and backreferences.
+    /// Equivalent to --engine pcre2.
+    #[clap(short = 'P', long)]
+    pub pcre2: bool,
+    /// Regex engine used for searching.
+    #[clap(long, value_enum, default_value_t = Engine::Default)]
+    pub engine: Engine,
     /// Search with pattern contains newline character ('\n').
     #[clap(short = 'U', long = "multiline")]
     pub multi_line: bool,
+    /// Show lines that do not match the given patterns.
+    #[clap(short = 'v', long = "invert-match")]
+    pub invert_match: bool,
+    /// Text encoding of searched files, e.g. utf-16le, latin1 or shift_jis.
+    /// By default, encoding is detected from BOM and UTF-8 is assumed otherwise.
+    #[clap(short = 'E', long)]
+    pub encoding: Option<String>,
+    /// Search in compressed files (gzip, bzip2, xz, lz4, lzma, brotli, zstd).
+    /// Requires the corresponding decompression tool to be installed.
+    #[clap(short = 'z', long = "search-zip")]
+    pub search_zip: bool,
+    /// Search output of the given command instead of file contents. The command is run
+    /// for every searched file, with file path as its argument and file contents as stdin.
+    #[clap(long, value_name = "COMMAND")]
+    pub pre: Option<PathBuf>,
+    /// Only run the --pre command for files that match the given glob.
+    /// Multiple globs may be provided.
+    #[clap(long, value_name = "GLOB", requires = "pre")]
+    pub pre_glob: Vec<String>,
+    /// Descend at most NUM directories below the given paths.
+    #[clap(long, value_name = "NUM")]
+    pub max_depth: Option<usize>,
+    /// Skip files larger than NUM bytes. K, M and G suffixes are accepted, e.g. 10M.
+    #[clap(long, value_name = "NUM+SUFFIX?", value_parser = parse_filesize)]
+    pub max_filesize: Option<u64>,
+    /// Show at most NUM matches in each file.
+    #[clap(short = 'm', long, value_name = "NUM")]
+    pub max_count: Option<u64>,
+    /// Pause the search after NUM matches, it can be continued from the UI.
+    #[clap(long, value_name = "NUM")]
+    pub max_results: Option<usize>,
+    /// Pause the search once results take about NUM bytes of memory, it can be continued
+    /// from the UI. K, M and G suffixes are accepted, e.g. 500M.
+    #[clap(long, value_name = "NUM+SUFFIX?", value_parser = parse_filesize)]
+    pub max_memory: Option<u64>,
+    /// Do not descend into directories on other file systems than the one a path starts on.
+    #[clap(long)]
+    pub one_file_system: bool,
+    /// Write paths which could not be walked or searched, with the reason, to FILE.
+    #[clap(long, value_name = "FILE")]
+    pub error_log: Option<PathBuf>,
     /// Include files and directories for searching that match the given glob.
     /// Multiple globs may be provided.
     #[clap(short, long)]
     pub glob: Vec<String>,
     /// Show all supported file types and their corresponding globs.
-    #[arg(group = "pattern_or_type", required = true)]
+    #[arg(group = "pattern_or_type")]
     #[clap(long)]
     pub type_list: bool,
     /// Only search files matching TYPE. Multiple types may be provided.
@@ -68,6 +163,24 @@ pub struct Args {
     /// Do not search files matching TYPE-NOT. Multiple types-not may be provided.
     #[clap(short = 'T', long)]
     pub type_not: Vec<String>,
+    /// Add a new glob for a file type, e.g. 'proto:*.proto'. Globs of another type can be
+    /// included with 'name:include:other'. Multiple definitions may be provided.
+    #[clap(long, value_name = "TYPE_SPEC")]
+    pub type_add: Vec<String>,
+    /// Clear the globs of a file type, applied before --type-add.
+    /// Multiple types may be provided.
+    #[clap(long, value_name = "TYPE")]
+    pub type_clear: Vec<String>,
+    /// Show NUM lines after each match.
+    #[clap(short = 'A', long, value_name = "NUM")]
+    pub after_context: Option<usize>,
+    /// Show NUM lines before each match.
+    #[clap(short = 'B', long, value_name = "NUM")]
+    pub before_context: Option<usize>,
+    /// Show NUM lines before and after each match.
+    /// Overridden by --after-context and --before-context.
+    #[clap(short = 'C', long, value_name = "NUM")]
+    pub context: Option<usize>,
     /// Context viewer position at startup
     #[clap(long, value_enum, default_value_t = ContextViewerPosition::None)]
     pub context_viewer: ContextViewerPosition,
@@ -77,6 +190,10 @@ pub struct Args {
     /// Sort results reverse, see ripgrep for details
     #[clap(long = "sortr")]
     pub sort_by_reverse: Option<SortKeyArg>,
+    /// Do not restart the search on every edit in the search pattern popup,
+    /// search only after confirming a pattern. Useful on slow filesystems.
+    #[clap(long)]
+    pub no_search_as_you_type: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -98,9 +215,39 @@ pub enum SortKeyArg {
     Modified,
     Created,
     Accessed,
+    Matches,
+    Size,
+    Depth,
+    Natural,
 }
 
 impl Args {
+    /// Collects patterns provided with --regexp and --file. If there are none, the positional
+    /// PATTERN argument is used, otherwise it is moved to the beginning of paths.
+    pub fn take_patterns(&mut self) -> Result<Vec<String>> {
+        let mut patterns = std::mem::take(&mut self.regexps);
+        for pattern_file in &self.pattern_files {
+            let file = File::open(pattern_file)
+                .with_context(|| format!("Cannot open pattern file {}", pattern_file.display()))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.with_context(|| {
+                    format!("Cannot read pattern file {}", pattern_file.display())
+                })?;
+                if !line.is_empty() {
+                    patterns.push(line);
+                }
+            }
+        }
+
+        match self.pattern.take() {
+            Some(pattern) if patterns.is_empty() => patterns.push(pattern),
+            Some(path) => self.paths.insert(0, path.into()),
+            None => (),
+        }
+
+        Ok(patterns)
+    }
+
     pub fn parse_cli_and_config_file() -> Self {
         // first validate if CLI arguments are valid
         Args::parse_from(std::env::args_os());
@@ -223,6 +370,22 @@ impl Args {
     }
 }
 
+fn parse_filesize(size: &str) -> Result<u64, String> {
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((index, 'K')) => (&size[..index], 1 << 10),
+        Some((index, 'M')) => (&size[..index], 1 << 20),
+        Some((index, 'G')) => (&size[..index], 1 << 30),
+        _ => (size, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| {
+            format!("invalid size '{size}', expected a number with optional K, M or G suffix")
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -389,6 +552,110 @@ mod tests {
         assert_eq!(args, ["-b", "value"]);
     }
 
+    #[test]
+    fn positional_pattern() {
+        let mut args = Args::parse_from(["ig", "pattern", "path"]);
+        assert_eq!(args.take_patterns().unwrap(), ["pattern"]);
+        assert_eq!(args.paths, [PathBuf::from("path")]);
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let mut args =
+            Args::parse_from(["ig", "-e", "pattern1", "path1", "-e", "pattern2", "path2"]);
+        assert_eq!(args.take_patterns().unwrap(), ["pattern1", "pattern2"]);
+        assert_eq!(args.paths, [PathBuf::from("path1"), PathBuf::from("path2")]);
+    }
+
+    #[test]
+    fn patterns_from_file() {
+        let pattern_file = std::env::temp_dir().join("igrep_patterns_from_file");
+        std::fs::write(&pattern_file, "pattern2\n\npattern3\n").unwrap();
+
+        let mut args = Args::parse_from([
+            "ig".into(),
+            "-e".into(),
+            "pattern1".into(),
+            "-f".into(),
+            pattern_file.clone().into_os_string(),
+        ]);
+        assert_eq!(
+            args.take_patterns().unwrap(),
+            ["pattern1", "pattern2", "pattern3"]
+        );
+        assert!(args.paths.is_empty());
+
+        std::fs::remove_file(pattern_file).unwrap();
+    }
+
+    #[test]
+    fn pattern_required() {
+        assert!(Args::try_parse_from(["ig"]).is_err());
+        assert!(Args::try_parse_from(["ig", "--type-list"]).is_ok());
+    }
+
+    #[test_case::test_case("100" => Ok(100))]
+    #[test_case::test_case("2K" => Ok(2048))]
+    #[test_case::test_case("10M" => Ok(10 * 1024 * 1024))]
+    #[test_case::test_case("1G" => Ok(1024 * 1024 * 1024))]
+    #[test_case::test_case("M" => matches Err(_); "suffix only")]
+    #[test_case::test_case("10T" => matches Err(_); "unknown suffix")]
+    fn filesize(size: &str) -> Result<u64, String> {
+        parse_filesize(size)
+    }
+
+    #[test]
+    fn limits_from_config_file() {
+        let input = "\
+            --max-depth=2
+            --max-filesize
+            10M
+            -m=5
+            --max-results=1000
+            --max-memory=1G
+            --one-file-system";
+
+        let config_args = Args::parse_from_reader(
+            input.as_bytes(),
+            Args::collect_supported_arguments(),
+            vec![],
+        );
+        let args = Args::parse_from(
+            ["ig".into(), "pattern".into()]
+                .into_iter()
+                .chain(config_args),
+        );
+
+        assert_eq!(args.max_depth, Some(2));
+        assert_eq!(args.max_filesize, Some(10 * 1024 * 1024));
+        assert_eq!(args.max_count, Some(5));
+        assert_eq!(args.max_results, Some(1000));
+        assert_eq!(args.max_memory, Some(1024 * 1024 * 1024));
+        assert!(args.one_file_system);
+    }
+
+    #[test]
+    fn type_add_from_config_file() {
+        let input = "\
+            --type-add
+            web:*.{html,css,js}*
+            --type-clear=md";
+
+        let config_args = Args::parse_from_reader(
+            input.as_bytes(),
+            Args::collect_supported_arguments(),
+            vec![],
+        );
+        let args = Args::parse_from(
+            ["ig".into(), "pattern".into()]
+                .into_iter()
+                .chain(config_args),
+        );
+
+        assert_eq!(args.type_add, ["web:*.{html,css,js}*"]);
+        assert_eq!(args.type_clear, ["md"]);
+    }
+
     #[test]
     fn pair_ignored() {
         let to_ignore = Args::pair_ignored(
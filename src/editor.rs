@@ -89,6 +89,11 @@ impl EditorCommand {
         let path = which::which(self.program())?;
         let mut command = Command::new(path);
         command.args(self.args(file_name, line_number));
+        #[cfg(unix)]
+        if !std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+            // standard input is taken by piped data, the editor needs the terminal
+            command.stdin(std::fs::File::open("/dev/tty")?);
+        }
         command.spawn().map_err(anyhow::Error::from)
     }
 
@@ -0,0 +1,478 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use crate::ig::{Engine, SearchConfig};
+
+/// Searches of previous sessions, recalled in the search pattern popup.
+///
+/// Searches are stored one per line, as tab separated paths, flags and patterns.
+/// Only searches of the same paths are recalled, so recalling does not change them.
+#[derive(Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    /// Canonical paths of the current search, separated by newlines.
+    paths: String,
+    /// Searches of all paths, oldest first.
+    entries: Vec<HistoryEntry>,
+    /// Index into searches of the current paths, counted from the newest one.
+    position: Option<usize>,
+    /// Search edited before browsing the history, restored when going past the newest search.
+    draft: RecalledSearch,
+    /// Text searched for with Ctrl-R and the patterns it recalled.
+    query: Option<(String, Vec<String>)>,
+}
+
+/// Patterns of a search and the flags they were searched with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecalledSearch {
+    pub patterns: Vec<String>,
+    pub flags: SearchFlags,
+}
+
+/// Options changing what patterns match, recalled together with them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchFlags {
+    case_insensitive: bool,
+    case_smart: bool,
+    word_regexp: bool,
+    fixed_strings: bool,
+    multi_line: bool,
+    invert_match: bool,
+    engine: Engine,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct HistoryEntry {
+    paths: String,
+    search: RecalledSearch,
+}
+
+impl History {
+    /// Number of searches kept in the file, the oldest are dropped first.
+    const MAX_ENTRIES: usize = 1000;
+
+    /// Loads the history file from `$XDG_DATA_HOME/igrep/history`, or from
+    /// `~/.local/share/igrep/history` if the variable is not set.
+    /// History is empty if the file cannot be read.
+    pub fn load(search_config: &SearchConfig) -> Self {
+        let data_dir = std::env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            });
+        let paths = search_config
+            .paths
+            .iter()
+            .map(|path| {
+                if search_config.stdin_file.as_deref() == Some(path) {
+                    return search_config.display_path(path);
+                }
+                std::fs::canonicalize(path)
+                    .as_deref()
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .join("\n");
+
+        Self::from_file(data_dir.map(|dir| dir.join("igrep").join("history")), paths)
+    }
+
+    fn from_file(path: Option<PathBuf>, paths: String) -> Self {
+        let entries = path.as_deref().and_then(Self::read).unwrap_or_default();
+
+        Self {
+            path,
+            paths,
+            entries,
+            ..Default::default()
+        }
+    }
+
+    fn read(path: &Path) -> Option<Vec<HistoryEntry>> {
+        let content = std::fs::read_to_string(path).ok()?;
+        Some(content.lines().filter_map(HistoryEntry::parse).collect())
+    }
+
+    /// Records the search, moving an identical one to the end instead of duplicating it.
+    pub fn add(&mut self, search_config: &SearchConfig) {
+        self.reset();
+        if search_config.patterns.iter().all(String::is_empty) {
+            return;
+        }
+
+        // searches recorded by other sessions since loading the file are kept
+        if let Some(entries) = self.path.as_deref().and_then(Self::read) {
+            self.entries = entries;
+        }
+        let entry = HistoryEntry::new(self.paths.clone(), search_config);
+        self.entries.retain(|existing| *existing != entry);
+        self.entries.push(entry);
+        if self.entries.len() > Self::MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - Self::MAX_ENTRIES);
+        }
+
+        if let Some(path) = &self.path {
+            Self::save(path, &self.entries).ok();
+        }
+    }
+
+    /// Writes a temporary file first, so that other sessions never read a partially written one.
+    fn save(path: &Path, entries: &[HistoryEntry]) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&temp_path)?);
+        for entry in entries {
+            writeln!(file, "{}", entry.format())?;
+        }
+        file.flush()?;
+        drop(file);
+        std::fs::rename(&temp_path, path)
+    }
+
+    /// Starts browsing from the newest search again.
+    pub fn reset(&mut self) {
+        self.position = None;
+        self.draft = RecalledSearch::default();
+        self.query = None;
+    }
+
+    fn project_entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.paths == self.paths)
+            .dedup_by(|lhs, rhs| lhs.search == rhs.search)
+    }
+
+    /// Returns the search older than the recalled one.
+    pub fn older(&mut self, current: RecalledSearch) -> Option<RecalledSearch> {
+        let position = self.position.map_or(0, |position| position + 1);
+        let search = self.project_entries().nth(position)?.search.clone();
+        if self.position.is_none() {
+            self.draft = current;
+        }
+        self.position = Some(position);
+        Some(search)
+    }
+
+    /// Returns the search newer than the recalled one,
+    /// or the search edited before browsing after the newest search.
+    pub fn newer(&mut self) -> Option<RecalledSearch> {
+        match self.position? {
+            0 => {
+                self.position = None;
+                Some(std::mem::take(&mut self.draft))
+            }
+            position => {
+                self.position = Some(position - 1);
+                self.project_entries()
+                    .nth(position - 1)
+                    .map(|entry| entry.search.clone())
+            }
+        }
+    }
+
+    /// Returns the newest search, older than the recalled one, with a pattern containing
+    /// the current text. Repeated searches continue with the same text until it is edited.
+    pub fn search(&mut self, current: RecalledSearch) -> Option<RecalledSearch> {
+        let (query, start) = match &self.query {
+            Some((query, recalled)) if *recalled == current.patterns => (
+                query.clone(),
+                self.position.map_or(0, |position| position + 1),
+            ),
+            _ => (current.patterns.join(" "), 0),
+        };
+
+        let (position, search) = self
+            .project_entries()
+            .enumerate()
+            .skip(start)
+            .find(|(_, entry)| entry.search.patterns.iter().any(|p| p.contains(&query)))
+            .map(|(position, entry)| (position, entry.search.clone()))?;
+
+        if self.position.is_none() {
+            self.draft = current;
+        }
+        self.position = Some(position);
+        self.query = Some((query, search.patterns.clone()));
+        Some(search)
+    }
+}
+
+impl SearchFlags {
+    pub fn new(search_config: &SearchConfig) -> Self {
+        Self {
+            case_insensitive: search_config.case_insensitive,
+            case_smart: search_config.case_smart,
+            word_regexp: search_config.word_regexp,
+            fixed_strings: search_config.fixed_strings,
+            multi_line: search_config.multi_line,
+            invert_match: search_config.invert_match,
+            engine: search_config.engine,
+        }
+    }
+
+    pub fn apply(self, search_config: &mut SearchConfig) {
+        search_config.case_insensitive = self.case_insensitive;
+        search_config.case_smart = self.case_smart;
+        search_config.word_regexp = self.word_regexp;
+        search_config.fixed_strings = self.fixed_strings;
+        search_config.multi_line = self.multi_line;
+        search_config.invert_match = self.invert_match;
+        search_config.engine = self.engine;
+    }
+
+    /// Formats flags as they are given on the command line, e.g. `-i -w --engine=auto`.
+    fn format(self) -> String {
+        let engine = (self.engine != Engine::Default)
+            .then(|| self.engine.to_possible_value())
+            .flatten()
+            .map(|engine| format!("--engine={}", engine.get_name()));
+        [
+            (self.case_insensitive, "-i"),
+            (self.case_smart, "-S"),
+            (self.word_regexp, "-w"),
+            (self.fixed_strings, "-F"),
+            (self.multi_line, "-U"),
+            (self.invert_match, "-v"),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, flag)| flag.to_string())
+        .chain(engine)
+        .join(" ")
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut flags = Self::default();
+        for flag in text.split_whitespace() {
+            match flag {
+                "-i" => flags.case_insensitive = true,
+                "-S" => flags.case_smart = true,
+                "-w" => flags.word_regexp = true,
+                "-F" => flags.fixed_strings = true,
+                "-U" => flags.multi_line = true,
+                "-v" => flags.invert_match = true,
+                _ => {
+                    if let Some(engine) = flag
+                        .strip_prefix("--engine=")
+                        .and_then(|name| Engine::from_str(name, false).ok())
+                    {
+                        flags.engine = engine;
+                    }
+                }
+            }
+        }
+        flags
+    }
+}
+
+impl HistoryEntry {
+    fn new(paths: String, search_config: &SearchConfig) -> Self {
+        Self {
+            paths,
+            search: RecalledSearch {
+                patterns: search_config
+                    .patterns
+                    .iter()
+                    .filter(|pattern| !pattern.is_empty())
+                    .cloned()
+                    .collect(),
+                flags: SearchFlags::new(search_config),
+            },
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t').map(unescape);
+        let paths = fields.next()?;
+        let flags = SearchFlags::parse(&fields.next()?);
+        let patterns = fields.collect::<Vec<_>>();
+        (!patterns.is_empty()).then_some(Self {
+            paths,
+            search: RecalledSearch { patterns, flags },
+        })
+    }
+
+    fn format(&self) -> String {
+        [self.paths.clone(), self.search.flags.format()]
+            .iter()
+            .chain(&self.search.patterns)
+            .map(|field| escape(field))
+            .join("\t")
+    }
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(patterns: &[&str]) -> SearchConfig {
+        SearchConfig::from(
+            patterns.iter().map(|p| p.to_string()).collect(),
+            vec!["src".into()],
+        )
+        .unwrap()
+    }
+
+    fn search(patterns: &[&str]) -> RecalledSearch {
+        RecalledSearch {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            flags: SearchFlags::default(),
+        }
+    }
+
+    fn history(entries: &[(&str, &[&str])]) -> History {
+        let mut history = History {
+            paths: "/project".into(),
+            ..Default::default()
+        };
+        for (paths, patterns) in entries {
+            history.paths = paths.to_string();
+            history.add(&config(patterns));
+        }
+        history.paths = "/project".into();
+        history
+    }
+
+    #[test]
+    fn browse_previous_and_next() {
+        let mut history = history(&[
+            ("/project", &["a"]),
+            ("/other", &["b"]),
+            ("/project", &["c"]),
+        ]);
+        let draft = search(&["draft"]);
+
+        assert_eq!(history.older(draft.clone()), Some(search(&["c"])));
+        assert_eq!(history.older(draft.clone()), Some(search(&["a"])));
+        assert_eq!(history.older(draft.clone()), None);
+        assert_eq!(history.newer(), Some(search(&["c"])));
+        assert_eq!(history.newer(), Some(draft));
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn duplicates_are_moved_to_the_end() {
+        let mut history = history(&[
+            ("/project", &["a"]),
+            ("/project", &["b"]),
+            ("/project", &["a"]),
+        ]);
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.older(search(&[])), Some(search(&["a"])));
+        assert_eq!(history.older(search(&[])), Some(search(&["b"])));
+    }
+
+    #[test]
+    fn reverse_search_continues_with_the_same_text() {
+        let mut history = history(&[
+            ("/project", &["foo_1"]),
+            ("/project", &["bar"]),
+            ("/project", &["x", "foo_2"]),
+        ]);
+
+        let found = history.search(search(&["foo"]));
+        assert_eq!(found, Some(search(&["x", "foo_2"])));
+        let found = history.search(found.unwrap());
+        assert_eq!(found, Some(search(&["foo_1"])));
+        assert_eq!(history.search(found.unwrap()), None);
+        assert_eq!(history.search(search(&["ba"])), Some(search(&["bar"])));
+    }
+
+    #[test]
+    fn flags_are_recalled() {
+        let mut history = History::default();
+        let mut search_config = config(&["a"]).case_insensitive(true).word_regexp(true);
+        search_config.engine = Engine::Auto;
+        history.add(&search_config);
+
+        let recalled = history.older(search(&[])).unwrap();
+        assert_eq!(recalled.flags.format(), "-i -w --engine=auto");
+        assert_eq!(SearchFlags::parse(&recalled.flags.format()), recalled.flags);
+
+        let mut recalled_config = config(&["a"]);
+        recalled.flags.apply(&mut recalled_config);
+        assert_eq!(SearchFlags::new(&recalled_config), recalled.flags);
+    }
+
+    #[test]
+    fn saved_and_loaded() {
+        let path = std::env::temp_dir()
+            .join(format!("igrep-history-{}", std::process::id()))
+            .join("history");
+        let mut history = History::from_file(Some(path.clone()), "/project".into());
+        history.add(&config(&["tab\there", "back\\slash"]).case_insensitive(true));
+
+        let mut loaded = History::from_file(Some(path.clone()), "/project".into());
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+
+        assert_eq!(loaded.entries, history.entries);
+        assert_eq!(loaded.entries[0].search.flags.format(), "-i");
+        assert_eq!(
+            loaded.older(search(&[])).map(|search| search.patterns),
+            Some(vec!["tab\there".into(), "back\\slash".into()])
+        );
+    }
+
+    #[test]
+    fn searches_of_other_sessions_are_kept() {
+        let path = std::env::temp_dir()
+            .join(format!("igrep-history-sessions-{}", std::process::id()))
+            .join("history");
+        let mut first = History::from_file(Some(path.clone()), "/project".into());
+        let mut second = History::from_file(Some(path.clone()), "/project".into());
+        first.add(&config(&["a"]));
+        second.add(&config(&["b"]));
+
+        let mut loaded = History::from_file(Some(path.clone()), "/project".into());
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+
+        assert_eq!(loaded.older(search(&[])), Some(search(&["b"])));
+        assert_eq!(loaded.older(search(&[])), Some(search(&["a"])));
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let mut history = History::default();
+        for index in 0..=History::MAX_ENTRIES {
+            history.add(&config(&[&index.to_string()]));
+        }
+        assert_eq!(history.entries.len(), History::MAX_ENTRIES);
+        assert_eq!(history.entries[0].search.patterns, ["1"]);
+    }
+}
@@ -1,20 +1,33 @@
 pub mod file_entry;
+pub mod file_reader;
 pub mod grep_match;
+mod pattern_error;
 pub mod search_config;
+mod search_control;
 mod searcher;
 mod sink;
 
+use std::fs::File;
+use std::io::Write;
 use std::process::ExitStatus;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
 use crate::editor::EditorCommand;
 use crate::ui::result_list::ResultList;
+pub use pattern_error::PatternError;
+pub use search_config::Engine;
 pub use search_config::SearchConfig;
 pub use search_config::SortKey;
+use search_control::SearchControl;
 use searcher::Event;
+pub use searcher::SearchStats;
 
 use self::file_entry::FileEntry;
 
+/// Number of entries buffered between the searcher and the UI, the searcher waits when it is full.
+const EVENTS_CAPACITY: usize = 256;
+
 #[derive(PartialEq, Eq)]
 pub enum State {
     Idle,
@@ -25,21 +38,38 @@ pub enum State {
 }
 
 pub struct Ig {
-    tx: mpsc::Sender<Event>,
+    tx: mpsc::SyncSender<Event>,
     rx: mpsc::Receiver<Event>,
     state: State,
+    stats: SearchStats,
+    search_started: Instant,
+    search_duration: Option<Duration>,
+    errors: Vec<String>,
+    pattern_error: Option<PatternError>,
+    error_log: Option<File>,
     editor_command: EditorCommand,
+    generation: usize,
+    control: Arc<SearchControl>,
 }
 
 impl Ig {
-    pub fn new(editor_command: EditorCommand) -> Self {
-        let (tx, rx) = mpsc::channel();
+    /// Errors of all searches are appended to the `error_log`.
+    pub fn new(editor_command: EditorCommand, error_log: Option<File>) -> Self {
+        let (tx, rx) = mpsc::sync_channel(EVENTS_CAPACITY);
 
         Self {
             tx,
             rx,
             state: State::Idle,
+            stats: SearchStats::default(),
+            search_started: Instant::now(),
+            search_duration: None,
+            errors: Vec::new(),
+            pattern_error: None,
+            error_log,
             editor_command,
+            generation: 0,
+            control: Arc::default(),
         }
     }
 
@@ -69,21 +99,97 @@ impl Ig {
     pub fn handle_searcher_event(&mut self) -> Option<FileEntry> {
         while let Ok(event) = self.rx.try_recv() {
             match event {
-                Event::NewEntry(e) => return Some(e),
-                Event::SearchingFinished => self.state = State::Idle,
-                Event::Error => self.state = State::Exit,
+                Event::NewEntry(generation, e) if generation == self.generation => {
+                    return Some(e);
+                }
+                Event::Progress(generation, stats, errors) if generation == self.generation => {
+                    self.stats = stats;
+                    self.add_errors(errors);
+                }
+                Event::SearchingFinished(generation) if generation == self.generation => {
+                    self.search_duration = Some(self.search_started.elapsed());
+                    self.state = State::Idle
+                }
+                Event::Error(generation) if generation == self.generation => {
+                    self.state = State::Exit
+                }
+                // leftovers of a cancelled search
+                _ => (),
             }
         }
 
         None
     }
 
-    pub fn search(&mut self, search_config: SearchConfig, result_list: &mut ResultList) {
-        if self.state == State::Idle {
-            *result_list = ResultList::default();
-            self.state = State::Searching;
-            searcher::search(search_config, self.tx.clone());
+    fn add_errors(&mut self, errors: Vec<String>) {
+        if let Some(error_log) = &mut self.error_log {
+            for error in &errors {
+                writeln!(error_log, "{error}").ok();
+            }
         }
+        self.errors.extend(errors);
+    }
+
+    /// Starts a new search, cancelling the one in progress.
+    /// Returns `false` if nothing was started, e.g. because the pattern does not compile.
+    pub fn search(&mut self, search_config: SearchConfig, result_list: &mut ResultList) -> bool {
+        if matches!(self.state, State::OpenFile(_) | State::Exit) {
+            return false;
+        }
+
+        let matcher = match searcher::build_matcher(&search_config) {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                self.pattern_error = Some(
+                    PatternError::find(&search_config.patterns, |patterns| {
+                        let mut search_config = search_config.clone();
+                        search_config.patterns = patterns.to_vec();
+                        // word boundaries wrap the pattern, which shifts offsets reported by PCRE2
+                        search_config.word_regexp = false;
+                        searcher::build_matcher(&search_config)
+                    })
+                    .unwrap_or_else(|| PatternError {
+                        pattern_index: 0,
+                        column: None,
+                        message: err.to_string(),
+                    }),
+                );
+                return false;
+            }
+        };
+        self.pattern_error = None;
+
+        self.cancel_search();
+
+        *result_list = ResultList::new(search_config.sort_by);
+        self.state = State::Searching;
+        self.stats = SearchStats::default();
+        self.search_started = Instant::now();
+        self.search_duration = None;
+        self.errors.clear();
+        self.generation += 1;
+        self.control = Arc::new(SearchControl::new(
+            search_config.max_results,
+            search_config.max_memory,
+        ));
+        searcher::search(
+            search_config,
+            matcher,
+            self.generation,
+            self.control.clone(),
+            self.tx.clone(),
+        );
+
+        true
+    }
+
+    /// Resumes a search paused by reaching the limit of results.
+    pub fn continue_search(&mut self) {
+        self.control.continue_search();
+    }
+
+    fn cancel_search(&self) {
+        self.control.cancel();
     }
 
     pub fn open_file(&mut self) {
@@ -102,6 +208,11 @@ impl Ig {
         self.state == State::Searching
     }
 
+    /// Searching, but no more files are searched until the search is continued.
+    pub fn is_paused(&self) -> bool {
+        self.is_searching() && self.control.is_paused()
+    }
+
     pub fn last_error(&self) -> Option<&str> {
         if let State::Error(err) = &self.state {
             Some(err)
@@ -110,6 +221,26 @@ impl Ig {
         }
     }
 
+    /// Error of the pattern of the last search which could not be started.
+    pub fn pattern_error(&self) -> Option<&PatternError> {
+        self.pattern_error.as_ref()
+    }
+
+    /// Paths which could not be walked or searched, with the reason.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    /// Duration of the last search, or of the current one so far.
+    pub fn elapsed(&self) -> Duration {
+        self.search_duration
+            .unwrap_or_else(|| self.search_started.elapsed())
+    }
+
     pub fn exit_requested(&self) -> bool {
         self.state == State::Exit
     }
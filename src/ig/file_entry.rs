@@ -1,48 +1,76 @@
 use super::grep_match::GrepMatch;
 
+#[derive(Clone)]
 pub enum EntryType {
     Header(String),
-    Match(u64, String, Vec<(usize, usize)>),
+    Match(u64, String, Vec<(usize, usize, usize)>),
+    Context(u64, String),
+    Separator,
+}
+
+impl EntryType {
+    pub fn is_match(&self) -> bool {
+        matches!(self, EntryType::Match(_, _, _))
+    }
 }
 
 pub struct FileEntry(Vec<EntryType>);
 
 impl FileEntry {
     pub fn new(name: String, matches: Vec<GrepMatch>) -> Self {
-        Self(
-            std::iter::once(EntryType::Header(name))
-                .chain(matches.into_iter().map(|m| {
-                    let mut text = String::new();
-                    let mut ofs = m.match_offsets;
-                    let mut pos = 0;
-                    for c in m.text.chars() {
-                        pos += 1;
-                        if c != '\t' {
-                            text.push(c);
-                        } else {
-                            text.push_str("  ");
-                            for p in &mut ofs {
-                                if p.0 >= pos {
-                                    p.0 += 1;
-                                    p.1 += 1;
-                                }
-                            }
-                        }
-                    }
-                    EntryType::Match(m.line_number, text, ofs)
-                }))
-                .collect(),
-        )
+        let mut entries = vec![EntryType::Header(name)];
+        let mut previous: Option<(u64, bool)> = None;
+
+        for m in matches {
+            // lines can be non-adjacent only when context is shown, separate such groups
+            if let Some((previous_line_number, previous_is_context)) = previous {
+                if m.line_number > previous_line_number + 1 && (m.is_context || previous_is_context)
+                {
+                    entries.push(EntryType::Separator);
+                }
+            }
+            previous = Some((m.line_number, m.is_context));
+
+            let (text, offsets) = expand_tabs(&m.text, m.match_offsets);
+            entries.push(if m.is_context {
+                EntryType::Context(m.line_number, text)
+            } else {
+                EntryType::Match(m.line_number, text, offsets)
+            });
+        }
+
+        Self(entries)
     }
 
     pub fn get_matches_count(&self) -> usize {
-        self.0
-            .iter()
-            .filter(|&e| matches!(e, EntryType::Match(_, _, _)))
-            .count()
+        self.0.iter().filter(|&e| e.is_match()).count()
     }
 
     pub fn get_entries(self) -> Vec<EntryType> {
         self.0
     }
 }
+
+fn expand_tabs(
+    line: &str,
+    mut offsets: Vec<(usize, usize, usize)>,
+) -> (String, Vec<(usize, usize, usize)>) {
+    let mut text = String::new();
+    let mut pos = 0;
+    for c in line.chars() {
+        pos += 1;
+        if c != '\t' {
+            text.push(c);
+        } else {
+            text.push_str("  ");
+            for p in &mut offsets {
+                if p.0 >= pos {
+                    p.0 += 1;
+                    p.1 += 1;
+                }
+            }
+        }
+    }
+
+    (text, offsets)
+}
@@ -0,0 +1,233 @@
+use std::{
+    fs::{File, OpenOptions},
+    hash::BuildHasher,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use grep::cli::{CommandReader, DecompressionReader};
+
+use super::SearchConfig;
+
+/// Returns whether the file is searched through the preprocessor command.
+pub fn is_preprocessed(path: impl AsRef<Path>, config: &SearchConfig) -> bool {
+    config.pre.is_some()
+        && (config.pre_globs.is_empty()
+            || config
+                .pre_globs
+                .matched(path.as_ref(), false)
+                .is_whitelist())
+}
+
+/// Runs the preprocessor with the file as its argument and stdin, its stdout is searched.
+pub(crate) fn preprocess(path: &Path, pre: &Path) -> std::io::Result<CommandReader> {
+    let mut command = Command::new(pre);
+    command.arg(path).stdin(File::open(path)?);
+    Ok(CommandReader::new(&mut command)?)
+}
+
+/// Temporary copy of standard input, removed when dropped.
+pub struct CapturedStdin {
+    path: PathBuf,
+}
+
+impl CapturedStdin {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CapturedStdin {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Copies standard input to a temporary file, so it can be searched, previewed and opened
+/// in the editor like any other file. The file is readable only by the current user and is
+/// never an existing file or symlink, its name is not predictable.
+pub fn capture_stdin() -> std::io::Result<CapturedStdin> {
+    const ATTEMPTS: usize = 16;
+
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    for attempt in 0..ATTEMPTS {
+        // randomly keyed hasher makes the name unpredictable
+        let name = std::collections::hash_map::RandomState::new().hash_one((
+            seed,
+            std::process::id(),
+            attempt,
+        ));
+        let path = std::env::temp_dir().join(format!("igrep-stdin-{name:016x}"));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = match options.open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+
+        let captured = CapturedStdin { path };
+        std::io::copy(&mut std::io::stdin().lock(), &mut file)?;
+        return Ok(captured);
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        "cannot create a unique temporary file",
+    ))
+}
+
+/// Reads paths separated by NUL characters, or by newlines if there are none,
+/// from a file or from standard input if the path is `-`.
+pub fn read_file_list(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut content = Vec::new();
+    if path == Path::new("-") {
+        std::io::stdin().lock().read_to_end(&mut content)?;
+    } else {
+        File::open(path)?.read_to_end(&mut content)?;
+    }
+    Ok(parse_file_list(&content))
+}
+
+fn parse_file_list(content: &[u8]) -> Vec<PathBuf> {
+    let separator = if content.contains(&b'\0') {
+        b'\0'
+    } else {
+        b'\n'
+    };
+    content
+        .split(|&byte| byte == separator)
+        .map(|path| path.strip_suffix(b"\r").unwrap_or(path))
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+        .collect()
+}
+
+/// Reads a whole file the same way it is searched: preprocessed or decompressed if requested,
+/// transcoded from the encoding given in the config or detected from BOM,
+/// invalid UTF-8 sequences are replaced.
+pub fn read_to_string(path: impl AsRef<Path>, config: &SearchConfig) -> std::io::Result<String> {
+    let path = path.as_ref();
+    let file: Box<dyn Read> = match &config.pre {
+        Some(pre) if is_preprocessed(path, config) => Box::new(preprocess(path, pre)?),
+        _ if config.search_zip => Box::new(DecompressionReader::new(path)?),
+        _ => Box::new(File::open(path)?),
+    };
+    let mut reader = DecodeReaderBytesBuilder::new()
+        .encoding(config.encoding)
+        .utf8_passthru(true)
+        .build(file);
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(name: &str, content: &[u8], encoding: Option<&str>) -> String {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, content).unwrap();
+
+        let config = SearchConfig::from(vec![], vec![])
+            .unwrap()
+            .encoding(encoding.map(Into::into))
+            .unwrap();
+        let text = read_to_string(&path, &config).unwrap();
+
+        std::fs::remove_file(path).unwrap();
+        text
+    }
+
+    #[test]
+    fn newline_separated_file_list() {
+        assert_eq!(
+            parse_file_list(b"src/main.rs\r\n\nsrc/file name.rs\n"),
+            [
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/file name.rs")
+            ]
+        );
+    }
+
+    #[test]
+    fn nul_separated_file_list() {
+        assert_eq!(
+            parse_file_list(b"src/main.rs\0src/new\nline.rs\0"),
+            [
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/new\nline.rs")
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        let text = read("igrep_utf16_with_bom", b"\xff\xfea\x00b\x00\n\x00", None);
+        assert_eq!(text, "ab\n");
+    }
+
+    #[test]
+    fn explicit_encoding() {
+        let text = read("igrep_explicit_encoding", b"caf\xe9\n", Some("latin1"));
+        assert_eq!(text, "café\n");
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let text = read("igrep_invalid_utf8_is_replaced", b"caf\xe9\n", None);
+        assert_eq!(text, "caf\u{fffd}\n");
+    }
+
+    #[test]
+    fn compressed_file() {
+        let path = std::env::temp_dir().join("igrep_compressed_file");
+        std::fs::write(&path, "lorem ipsum\n").unwrap();
+        let Ok(status) = std::process::Command::new("gzip")
+            .arg("-f")
+            .arg(&path)
+            .status()
+        else {
+            // gzip is not installed
+            return;
+        };
+        assert!(status.success());
+
+        let compressed_path = path.with_extension("gz");
+        let config = SearchConfig::from(vec![], vec![]).unwrap().search_zip(true);
+        let text = read_to_string(&compressed_path, &config).unwrap();
+        assert_eq!(text, "lorem ipsum\n");
+
+        std::fs::remove_file(compressed_path).unwrap();
+    }
+
+    #[test]
+    fn preprocessed_file() {
+        let path = std::env::temp_dir().join("igrep_preprocessed_file.txt");
+        std::fs::write(&path, "lorem ipsum\n").unwrap();
+
+        let config = SearchConfig::from(vec![], vec![])
+            .unwrap()
+            .pre(Some("rev".into()), vec!["*.txt".into()])
+            .unwrap();
+        assert!(is_preprocessed(&path, &config));
+        assert!(!is_preprocessed("file.pdf", &config));
+
+        // rev might not be installed
+        if let Ok(text) = read_to_string(&path, &config) {
+            assert_eq!(text, "muspi merol\n");
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
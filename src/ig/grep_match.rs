@@ -1,15 +1,35 @@
 pub struct GrepMatch {
     pub line_number: u64,
     pub text: String,
-    pub match_offsets: Vec<(usize, usize)>,
+    /// Start, end and index of the matching pattern for every match in the line.
+    pub match_offsets: Vec<(usize, usize, usize)>,
+    pub is_context: bool,
 }
 
 impl GrepMatch {
-    pub fn new(line_number: u64, text: String, match_offsets: Vec<(usize, usize)>) -> Self {
+    pub fn new(line_number: u64, text: String, match_offsets: Vec<(usize, usize, usize)>) -> Self {
         Self {
             line_number,
             text,
             match_offsets,
+            is_context: false,
         }
     }
+
+    pub fn context(line_number: u64, text: String) -> Self {
+        Self {
+            line_number,
+            text,
+            match_offsets: Vec::new(),
+            is_context: true,
+        }
+    }
+
+    /// Approximate number of bytes taken by the match in memory.
+    pub fn memory_size(&self) -> u64 {
+        (std::mem::size_of::<Self>()
+            + self.text.capacity()
+            + self.match_offsets.capacity() * std::mem::size_of::<(usize, usize, usize)>())
+            as u64
+    }
 }
@@ -0,0 +1,155 @@
+use std::fmt::Display;
+
+/// Pattern rejected by the regex engine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternError {
+    /// Index of the rejected pattern among all searched patterns.
+    pub pattern_index: usize,
+    /// One-based column of the pattern the error points at, if the engine reports one.
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl PatternError {
+    /// Finds the first of `patterns` which `build` rejects on its own, as errors of
+    /// several patterns point into their combined alternation rather than into one of them.
+    pub fn find<T, E: Display>(
+        patterns: &[String],
+        build: impl Fn(&[String]) -> Result<T, E>,
+    ) -> Option<Self> {
+        patterns
+            .iter()
+            .enumerate()
+            .find_map(|(pattern_index, pattern)| {
+                build(std::slice::from_ref(pattern))
+                    .err()
+                    .map(|err| Self::new(pattern_index, pattern, &err.to_string()))
+            })
+    }
+
+    /// Parses the message of the regex parser, which quotes the compiled pattern
+    /// and marks the erroneous part with carets on the line below it:
+    ///
+    /// ```text
+    /// regex parse error:
+    ///     (?:a\qb)
+    ///         ^^
+    /// error: unrecognized escape sequence
+    /// ```
+    ///
+    /// PCRE2 reports the offset into the pattern instead:
+    ///
+    /// ```text
+    /// PCRE2: error compiling pattern at offset 3: missing closing parenthesis
+    /// ```
+    fn new(pattern_index: usize, pattern: &str, error: &str) -> Self {
+        if let Some((offset, message)) = parse_pcre2_error(error) {
+            return Self {
+                pattern_index,
+                column: (offset < pattern.len()).then_some(offset + 1),
+                message: message.into(),
+            };
+        }
+
+        let lines = error.lines().collect::<Vec<_>>();
+        let caret_line = lines
+            .iter()
+            .position(|line| line.trim_start().starts_with('^'));
+
+        let column = caret_line.filter(|&index| index > 0).and_then(|index| {
+            let quoted = lines[index - 1];
+            let pattern_offset = quoted.find(pattern)?;
+            let caret_offset = lines[index].find('^')?;
+            // carets may point at the group wrapping the pattern, e.g. when it is not closed
+            let column = caret_offset.checked_sub(pattern_offset)?;
+            (column < pattern.len()).then_some(column + 1)
+        });
+
+        let message = match caret_line {
+            Some(index) => lines[index + 1..]
+                .iter()
+                .map(|line| line.trim_start_matches("error: "))
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => error.lines().collect::<Vec<_>>().join(" "),
+        };
+
+        Self {
+            pattern_index,
+            column,
+            message,
+        }
+    }
+}
+
+/// Splits a PCRE2 compilation error into the offset it points at and its message.
+fn parse_pcre2_error(error: &str) -> Option<(usize, &str)> {
+    let rest = error.strip_prefix("PCRE2: error compiling pattern at offset ")?;
+    let (offset, message) = rest.split_once(": ")?;
+    Some((offset.parse().ok()?, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grep::regex::RegexMatcherBuilder;
+    use test_case::test_case;
+
+    fn find(patterns: &[&str]) -> Option<PatternError> {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        PatternError::find(&patterns, |patterns| {
+            RegexMatcherBuilder::new().build_many(patterns)
+        })
+    }
+
+    #[test_case(&["a\\qb"], 0, 2, "unrecognized escape sequence" ; "escape")]
+    #[test_case(&["ok", "x{2,1}"], 1, 2, "invalid repetition count range, the start must be <= the end" ; "second pattern")]
+    fn column_of_error(patterns: &[&str], pattern_index: usize, column: usize, message: &str) {
+        assert_eq!(
+            find(patterns),
+            Some(PatternError {
+                pattern_index,
+                column: Some(column),
+                message: message.into(),
+            })
+        );
+    }
+
+    #[test_case(&["a(b"], "unclosed group" ; "unclosed group")]
+    #[test_case(&["(?<x"], "invalid capture group character" ; "end of pattern")]
+    fn no_column_outside_of_pattern(patterns: &[&str], message: &str) {
+        assert_eq!(
+            find(patterns),
+            Some(PatternError {
+                pattern_index: 0,
+                column: None,
+                message: message.into(),
+            })
+        );
+    }
+
+    #[test]
+    fn valid_patterns() {
+        assert_eq!(find(&["a", "b+"]), None);
+    }
+
+    #[test_case("a\\", "PCRE2: error compiling pattern at offset 2: \\ at end of pattern", None, "\\ at end of pattern" ; "end of pattern")]
+    #[test_case("ab)c", "PCRE2: error compiling pattern at offset 2: unmatched closing parenthesis", Some(3), "unmatched closing parenthesis" ; "in pattern")]
+    fn pcre2_offset(pattern: &str, error: &str, column: Option<usize>, message: &str) {
+        assert_eq!(
+            PatternError::new(0, pattern, error),
+            PatternError {
+                pattern_index: 0,
+                column,
+                message: message.into(),
+            }
+        );
+    }
+
+    #[test]
+    fn message_without_column() {
+        let error = PatternError::new(0, "a", "PCRE2: error compiling pattern");
+        assert_eq!(error.column, None);
+        assert_eq!(error.message, "PCRE2: error compiling pattern");
+    }
+}
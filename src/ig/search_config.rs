@@ -1,12 +1,18 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
 use ignore::{
     overrides::{Override, OverrideBuilder},
     types::{Types, TypesBuilder},
 };
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::args::SortKeyArg;
 
+const STDIN_NAME: &str = "<stdin>";
+
 #[derive(Clone, Copy)]
 pub enum SortKey {
     Path,
@@ -17,11 +23,90 @@ pub enum SortKey {
     CreatedReversed,
     Accessed,
     AccessedReversed,
+    Matches,
+    MatchesReversed,
+    Size,
+    SizeReversed,
+    Depth,
+    DepthReversed,
+    Natural,
+    NaturalReversed,
+}
+
+impl SortKey {
+    fn new(arg: SortKeyArg, reversed: bool) -> Self {
+        match (arg, reversed) {
+            (SortKeyArg::Path, false) => SortKey::Path,
+            (SortKeyArg::Path, true) => SortKey::PathReversed,
+            (SortKeyArg::Modified, false) => SortKey::Modified,
+            (SortKeyArg::Modified, true) => SortKey::ModifiedReversed,
+            (SortKeyArg::Created, false) => SortKey::Created,
+            (SortKeyArg::Created, true) => SortKey::CreatedReversed,
+            (SortKeyArg::Accessed, false) => SortKey::Accessed,
+            (SortKeyArg::Accessed, true) => SortKey::AccessedReversed,
+            (SortKeyArg::Matches, false) => SortKey::Matches,
+            (SortKeyArg::Matches, true) => SortKey::MatchesReversed,
+            (SortKeyArg::Size, false) => SortKey::Size,
+            (SortKeyArg::Size, true) => SortKey::SizeReversed,
+            (SortKeyArg::Depth, false) => SortKey::Depth,
+            (SortKeyArg::Depth, true) => SortKey::DepthReversed,
+            (SortKeyArg::Natural, false) => SortKey::Natural,
+            (SortKeyArg::Natural, true) => SortKey::NaturalReversed,
+        }
+    }
+
+    /// Name of the key as accepted by `--sort` and `--sortr`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Path | SortKey::PathReversed => "path",
+            SortKey::Modified | SortKey::ModifiedReversed => "modified",
+            SortKey::Created | SortKey::CreatedReversed => "created",
+            SortKey::Accessed | SortKey::AccessedReversed => "accessed",
+            SortKey::Matches | SortKey::MatchesReversed => "matches",
+            SortKey::Size | SortKey::SizeReversed => "size",
+            SortKey::Depth | SortKey::DepthReversed => "depth",
+            SortKey::Natural | SortKey::NaturalReversed => "natural",
+        }
+    }
+
+    pub fn is_reversed(self) -> bool {
+        matches!(
+            self,
+            SortKey::PathReversed
+                | SortKey::ModifiedReversed
+                | SortKey::CreatedReversed
+                | SortKey::AccessedReversed
+                | SortKey::MatchesReversed
+                | SortKey::SizeReversed
+                | SortKey::DepthReversed
+                | SortKey::NaturalReversed
+        )
+    }
+
+    /// Keys which cannot be applied while walking directories, results are sorted instead.
+    pub fn is_applied_to_results(self) -> bool {
+        matches!(
+            self,
+            SortKey::Matches | SortKey::MatchesReversed | SortKey::Depth | SortKey::DepthReversed
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+    /// Rust regex engine, fast but without look-around and backreferences
+    #[default]
+    Default,
+    /// PCRE2 engine, requires igrep to be built with the `pcre2` feature
+    Pcre2,
+    /// Rust regex engine, PCRE2 if the pattern is not supported by the former and igrep
+    /// is built with the `pcre2` feature
+    Auto,
 }
 
 #[derive(Clone)]
 pub struct SearchConfig {
-    pub pattern: String,
+    pub patterns: Vec<String>,
     pub paths: Vec<PathBuf>,
     pub case_insensitive: bool,
     pub case_smart: bool,
@@ -33,16 +118,42 @@ pub struct SearchConfig {
     pub sort_by: Option<SortKey>,
     pub fixed_strings: bool,
     pub multi_line: bool,
+    pub invert_match: bool,
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+    pub search_zip: bool,
+    pub pre: Option<PathBuf>,
+    pub pre_globs: Override,
+    pub max_depth: Option<usize>,
+    pub max_filesize: Option<u64>,
+    pub max_count: Option<u64>,
+    pub max_results: Option<usize>,
+    pub max_memory: Option<u64>,
+    pub one_file_system: bool,
+    pub no_ignore: bool,
+    pub no_ignore_vcs: bool,
+    pub no_ignore_parent: bool,
+    pub no_ignore_files: bool,
+    pub ignore_files: Vec<PathBuf>,
+    pub search_binary: bool,
+    /// Level of `-u`, applied on top of the filtering flags given on the command line.
+    pub unrestricted: u8,
+    /// `no_ignore`, `search_hidden` and `search_binary` before applying the `-u` level.
+    unrestricted_base: (bool, bool, bool),
+    pub stdin_file: Option<PathBuf>,
+    pub file_list: Option<Arc<[PathBuf]>>,
+    pub after_context: usize,
+    pub before_context: usize,
+    pub engine: Engine,
 }
 
 impl SearchConfig {
-    pub fn from(pattern: String, paths: Vec<PathBuf>) -> Result<Self> {
+    pub fn from(patterns: Vec<String>, paths: Vec<PathBuf>) -> Result<Self> {
         let mut builder = TypesBuilder::new();
         builder.add_defaults();
         let types = builder.build()?;
 
         Ok(Self {
-            pattern,
+            patterns,
             paths,
             case_insensitive: false,
             case_smart: false,
@@ -53,7 +164,31 @@ impl SearchConfig {
             word_regexp: false,
             fixed_strings: false,
             multi_line: false,
+            invert_match: false,
+            encoding: None,
+            search_zip: false,
+            pre: None,
+            pre_globs: Override::empty(),
+            max_depth: None,
+            max_filesize: None,
+            max_count: None,
+            max_results: None,
+            max_memory: None,
+            one_file_system: false,
+            no_ignore: false,
+            no_ignore_vcs: false,
+            no_ignore_parent: false,
+            no_ignore_files: false,
+            ignore_files: Vec::new(),
+            search_binary: false,
+            unrestricted: 0,
+            unrestricted_base: (false, false, false),
+            stdin_file: None,
+            file_list: None,
             sort_by: None,
+            after_context: 0,
+            before_context: 0,
+            engine: Engine::Default,
         })
     }
 
@@ -76,13 +211,24 @@ impl SearchConfig {
         Ok(self)
     }
 
+    pub fn pre(mut self, pre: Option<PathBuf>, pre_globs: Vec<String>) -> Result<Self> {
+        let mut builder = OverrideBuilder::new(std::env::current_dir()?);
+        for glob in pre_globs {
+            builder.add(&glob)?;
+        }
+        self.pre = pre;
+        self.pre_globs = builder.build()?;
+        Ok(self)
+    }
+
     pub fn file_types(
         mut self,
         file_types: Vec<String>,
         file_types_not: Vec<String>,
+        type_add: &[String],
+        type_clear: &[String],
     ) -> Result<Self> {
-        let mut builder = TypesBuilder::new();
-        builder.add_defaults();
+        let mut builder = types_builder(type_add, type_clear)?;
         for file_type in file_types {
             builder.select(&file_type);
         }
@@ -99,20 +245,10 @@ impl SearchConfig {
         sort_by_reversed: Option<SortKeyArg>,
     ) -> Result<Self> {
         if let Some(arg) = sort_by {
-            match arg {
-                SortKeyArg::Path => self.sort_by = Some(SortKey::Path),
-                SortKeyArg::Modified => self.sort_by = Some(SortKey::Modified),
-                SortKeyArg::Created => self.sort_by = Some(SortKey::Created),
-                SortKeyArg::Accessed => self.sort_by = Some(SortKey::Accessed),
-            }
+            self.sort_by = Some(SortKey::new(arg, false));
         };
         if let Some(arg) = sort_by_reversed {
-            match arg {
-                SortKeyArg::Path => self.sort_by = Some(SortKey::PathReversed),
-                SortKeyArg::Modified => self.sort_by = Some(SortKey::ModifiedReversed),
-                SortKeyArg::Created => self.sort_by = Some(SortKey::CreatedReversed),
-                SortKeyArg::Accessed => self.sort_by = Some(SortKey::AccessedReversed),
-            }
+            self.sort_by = Some(SortKey::new(arg, true));
         };
         Ok(self)
     }
@@ -141,4 +277,246 @@ impl SearchConfig {
         self.multi_line = multi_line;
         self
     }
+
+    pub fn invert_match(mut self, invert_match: bool) -> Self {
+        self.invert_match = invert_match;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_filesize(mut self, max_filesize: Option<u64>) -> Self {
+        self.max_filesize = max_filesize;
+        self
+    }
+
+    pub fn max_count(mut self, max_count: Option<u64>) -> Self {
+        self.max_count = max_count;
+        self
+    }
+
+    pub fn max_results(mut self, max_results: Option<usize>) -> Self {
+        self.max_results = max_results;
+        self
+    }
+
+    pub fn max_memory(mut self, max_memory: Option<u64>) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    pub fn no_ignore(mut self, no_ignore: bool) -> Self {
+        self.no_ignore = no_ignore;
+        self
+    }
+
+    pub fn no_ignore_vcs(mut self, no_ignore_vcs: bool) -> Self {
+        self.no_ignore_vcs = no_ignore_vcs;
+        self
+    }
+
+    pub fn no_ignore_parent(mut self, no_ignore_parent: bool) -> Self {
+        self.no_ignore_parent = no_ignore_parent;
+        self
+    }
+
+    pub fn no_ignore_files(mut self, no_ignore_files: bool) -> Self {
+        self.no_ignore_files = no_ignore_files;
+        self
+    }
+
+    pub fn ignore_files(mut self, ignore_files: Vec<PathBuf>) -> Result<Self> {
+        for ignore_file in &ignore_files {
+            if !ignore_file.is_file() {
+                bail!("Ignore file not found: '{}'", ignore_file.display());
+            }
+        }
+        self.ignore_files = ignore_files;
+        Ok(self)
+    }
+
+    /// Lifts ignore rules like ripgrep's `-u` flags: 1 - ignore files are not respected,
+    /// 2 - hidden files are searched as well, 3 - binary files are searched as well.
+    /// Must be called after the builders of the flags it extends.
+    pub fn unrestricted(mut self, level: u8) -> Self {
+        self.unrestricted_base = (self.no_ignore, self.search_hidden, self.search_binary);
+        self.set_unrestricted_level(level);
+        self
+    }
+
+    /// Changes the `-u` level, flags given on the command line stay enabled on every level.
+    pub fn set_unrestricted_level(&mut self, level: u8) {
+        let (no_ignore, search_hidden, search_binary) = self.unrestricted_base;
+        self.unrestricted = level;
+        self.no_ignore = no_ignore || level >= 1;
+        self.search_hidden = search_hidden || level >= 2;
+        self.search_binary = search_binary || level >= 3;
+    }
+
+    /// Toggles respecting ignore files on top of the `-u` level. As every level lifts ignore
+    /// files, turning them back on drops the level, keeping hidden and binary files searched.
+    pub fn toggle_no_ignore(&mut self) {
+        self.no_ignore = !self.no_ignore;
+        if !self.no_ignore {
+            self.unrestricted = 0;
+        }
+        self.unrestricted_base = (self.no_ignore, self.search_hidden, self.search_binary);
+    }
+
+    /// Searches only the given files instead of walking the paths.
+    pub fn file_list(mut self, file_list: Option<Vec<PathBuf>>) -> Self {
+        self.file_list = file_list.map(Into::into);
+        self
+    }
+
+    /// Sets the temporary file holding captured standard input.
+    pub fn stdin_file(mut self, stdin_file: Option<PathBuf>) -> Self {
+        self.stdin_file = stdin_file;
+        self
+    }
+
+    /// Name under which a searched file is shown, captured standard input is shown as `<stdin>`.
+    pub fn display_path(&self, path: &Path) -> String {
+        if self.stdin_file.as_deref() == Some(path) {
+            STDIN_NAME.into()
+        } else {
+            path.to_string_lossy().into_owned()
+        }
+    }
+
+    /// Path of a file shown under the given name, reverse of [`SearchConfig::display_path`].
+    pub fn resolve_path(&self, name: &str) -> PathBuf {
+        match &self.stdin_file {
+            Some(stdin_file) if name == STDIN_NAME => stdin_file.clone(),
+            _ => PathBuf::from(name),
+        }
+    }
+
+    pub fn search_zip(mut self, search_zip: bool) -> Self {
+        self.search_zip = search_zip;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Option<String>) -> Result<Self> {
+        self.encoding = match encoding.as_deref() {
+            None | Some("auto") => None,
+            Some(label) => {
+                match encoding_rs::Encoding::for_label_no_replacement(label.as_bytes()) {
+                    Some(encoding) => Some(encoding),
+                    None => bail!("Unsupported encoding: '{label}'"),
+                }
+            }
+        };
+        Ok(self)
+    }
+
+    pub fn after_context(mut self, after_context: usize) -> Self {
+        self.after_context = after_context;
+        self
+    }
+
+    pub fn before_context(mut self, before_context: usize) -> Self {
+        self.before_context = before_context;
+        self
+    }
+
+    pub fn engine(mut self, engine: Engine) -> Result<Self> {
+        // auto falls back to the default engine, only an explicit request for PCRE2 fails
+        if engine == Engine::Pcre2 && !cfg!(feature = "pcre2") {
+            bail!("PCRE2 is not available, igrep was built without the `pcre2` feature");
+        }
+        self.engine = engine;
+        Ok(self)
+    }
+}
+
+/// Builder with the default file types, cleared and extended by the user definitions.
+pub fn types_builder(type_add: &[String], type_clear: &[String]) -> Result<TypesBuilder> {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    for name in type_clear {
+        builder.clear(name);
+    }
+    for definition in type_add {
+        builder.add_def(definition)?;
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unrestricted_level_keeps_command_line_flags() {
+        let mut config = SearchConfig::from(vec![], vec![])
+            .unwrap()
+            .search_hidden(true)
+            .unrestricted(1);
+        assert!(config.no_ignore && config.search_hidden && !config.search_binary);
+
+        config.set_unrestricted_level(3);
+        assert!(config.no_ignore && config.search_hidden && config.search_binary);
+
+        config.set_unrestricted_level(0);
+        assert!(!config.no_ignore && config.search_hidden && !config.search_binary);
+    }
+
+    #[test]
+    fn toggling_no_ignore_is_kept_by_unrestricted_level() {
+        let mut config = SearchConfig::from(vec![], vec![]).unwrap().unrestricted(0);
+        config.toggle_no_ignore();
+        config.set_unrestricted_level(0);
+        assert!(config.no_ignore);
+
+        config.set_unrestricted_level(2);
+        config.toggle_no_ignore();
+        assert!(!config.no_ignore && config.search_hidden && config.unrestricted == 0);
+
+        config.set_unrestricted_level(1);
+        assert!(config.no_ignore && config.search_hidden && !config.search_binary);
+    }
+
+    #[test]
+    fn auto_engine_is_always_available() {
+        let config = SearchConfig::from(vec![], vec![]).unwrap();
+        assert!(config.clone().engine(Engine::Auto).is_ok());
+        assert_eq!(
+            config.engine(Engine::Pcre2).is_ok(),
+            cfg!(feature = "pcre2")
+        );
+    }
+
+    #[test]
+    fn stdin_path() {
+        let config = SearchConfig::from(vec![], vec![])
+            .unwrap()
+            .stdin_file(Some("/tmp/igrep-stdin".into()));
+
+        assert_eq!(
+            config.display_path(Path::new("/tmp/igrep-stdin")),
+            "<stdin>"
+        );
+        assert_eq!(config.display_path(Path::new("./file")), "./file");
+        assert_eq!(
+            config.resolve_path("<stdin>"),
+            PathBuf::from("/tmp/igrep-stdin")
+        );
+        assert_eq!(config.resolve_path("./file"), PathBuf::from("./file"));
+    }
+
+    #[test]
+    fn no_stdin_path() {
+        let config = SearchConfig::from(vec![], vec![]).unwrap();
+
+        assert_eq!(config.resolve_path("<stdin>"), PathBuf::from("<stdin>"));
+    }
 }
@@ -0,0 +1,180 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Condvar, Mutex,
+};
+
+/// Shared by the UI and all threads of a single search. Cancels the search, and pauses it
+/// once its results reach `--max-results` or `--max-memory`, until it is continued.
+/// Threads pause in the middle of the file they are searching, which resumes where it stopped.
+#[derive(Default)]
+pub(crate) struct SearchControl {
+    cancelled: AtomicBool,
+    max_results: Option<usize>,
+    max_memory: Option<u64>,
+    budget: Mutex<Budget>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Budget {
+    results: usize,
+    memory: u64,
+    /// Every continuation raises the limits by their initial values.
+    continuations: usize,
+    /// Number of threads waiting for the search to be continued.
+    waiting: usize,
+}
+
+impl SearchControl {
+    pub(crate) fn new(max_results: Option<usize>, max_memory: Option<u64>) -> Self {
+        Self {
+            max_results,
+            max_memory,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        // taking the lock makes sure no thread misses the notification between its checks
+        let _budget = self.lock();
+        self.changed.notify_all();
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Waits while the limits are reached until the search is continued.
+    /// Returns `false` if the search is cancelled instead.
+    pub(crate) fn wait_for_budget(&self) -> bool {
+        if self.max_results.is_none() && self.max_memory.is_none() {
+            return !self.is_cancelled();
+        }
+
+        let mut budget = self.lock();
+        while !self.is_cancelled() && self.is_reached(&budget) {
+            budget.waiting += 1;
+            budget = self.changed.wait(budget).expect("Search budget poisoned");
+            budget.waiting -= 1;
+        }
+
+        !self.is_cancelled()
+    }
+
+    /// Accounts matches about to be added to the results. Returns `false` without accounting
+    /// them if the limits are already reached or the search is cancelled.
+    pub(crate) fn spend(&self, results: usize, memory: u64) -> bool {
+        if self.is_cancelled() {
+            return false;
+        }
+        if self.max_results.is_none() && self.max_memory.is_none() {
+            return true;
+        }
+
+        let mut budget = self.lock();
+        if self.is_reached(&budget) {
+            return false;
+        }
+        budget.results += results;
+        budget.memory += memory;
+        true
+    }
+
+    /// Resumes a search paused by reaching the limits.
+    pub(crate) fn continue_search(&self) {
+        let mut budget = self.lock();
+        if budget.waiting > 0 {
+            budget.continuations += 1;
+            self.changed.notify_all();
+        }
+    }
+
+    /// Whether a searcher thread waits for the search to be continued.
+    pub(crate) fn is_paused(&self) -> bool {
+        self.lock().waiting > 0
+    }
+
+    fn is_reached(&self, budget: &Budget) -> bool {
+        let factor = budget.continuations + 1;
+        self.max_results
+            .is_some_and(|max_results| budget.results >= max_results * factor)
+            || self
+                .max_memory
+                .is_some_and(|max_memory| budget.memory >= max_memory * factor as u64)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Budget> {
+        self.budget.lock().expect("Search budget poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread, time::Duration};
+
+    use super::*;
+
+    fn wait_until_paused(control: &SearchControl) {
+        while !control.is_paused() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn spends_up_to_max_results() {
+        let control = SearchControl::new(Some(2), None);
+        assert!(control.spend(1, 0));
+        assert!(control.spend(1, 0));
+        assert!(!control.spend(1, 0));
+        assert!(!control.is_paused());
+    }
+
+    #[test]
+    fn spends_up_to_max_memory() {
+        let control = SearchControl::new(None, Some(100));
+        assert!(control.spend(0, 60));
+        assert!(control.spend(0, 60));
+        assert!(!control.spend(0, 1));
+    }
+
+    #[test]
+    fn pauses_until_continued() {
+        let control = Arc::new(SearchControl::new(Some(1), None));
+        assert!(control.spend(1, 0));
+
+        let searcher = thread::spawn({
+            let control = control.clone();
+            move || control.wait_for_budget() && control.spend(1, 0)
+        });
+        wait_until_paused(&control);
+        control.continue_search();
+
+        assert!(searcher.join().unwrap());
+        assert!(!control.is_paused());
+    }
+
+    #[test]
+    fn cancelling_releases_paused_threads() {
+        let control = Arc::new(SearchControl::new(Some(1), None));
+        assert!(control.spend(1, 0));
+
+        let searcher = thread::spawn({
+            let control = control.clone();
+            move || control.wait_for_budget()
+        });
+        wait_until_paused(&control);
+        control.cancel();
+
+        assert!(!searcher.join().unwrap());
+        assert!(!control.spend(0, 0));
+    }
+
+    #[test]
+    fn continuing_a_running_search_does_nothing() {
+        let control = SearchControl::new(Some(1), None);
+        control.continue_search();
+        assert!(control.spend(1, 0));
+        assert!(!control.spend(1, 0));
+    }
+}
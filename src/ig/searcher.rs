@@ -1,87 +1,444 @@
-use super::{file_entry::FileEntry, sink::MatchesSink, SearchConfig};
+use super::{
+    file_entry::FileEntry,
+    file_reader,
+    search_control::SearchControl,
+    sink::{group_patterns, MatchesSink},
+    Engine, SearchConfig,
+};
 use crate::ig::SortKey;
+use anyhow::Result;
 use grep::{
-    matcher::LineTerminator,
-    regex::RegexMatcherBuilder,
-    searcher::{BinaryDetection, SearcherBuilder},
+    cli::DecompressionReaderBuilder,
+    matcher::{LineTerminator, Matcher},
+    regex::{RegexMatcher, RegexMatcherBuilder},
+    searcher::{BinaryDetection, Encoding, Searcher, SearcherBuilder},
 };
 use ignore::WalkBuilder;
+use itertools::{EitherOrBoth, Itertools};
 use std::cmp::Ordering;
-use std::{path::Path, sync::mpsc};
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicU64, AtomicUsize},
+        mpsc, Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Tool-specific ignore file, respected like `.ignore`.
+const IGREP_IGNORE_FILENAME: &str = ".igrepignore";
+
+/// Interval between progress events sent while searching.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
+/// Every event carries the generation of the search that produced it,
+/// so that events of a cancelled search can be told apart and dropped.
 pub enum Event {
-    NewEntry(FileEntry),
-    SearchingFinished,
-    Error,
+    NewEntry(usize, FileEntry),
+    /// Current statistics and errors which occurred since the previous progress event.
+    Progress(usize, SearchStats, Vec<String>),
+    SearchingFinished(usize),
+    Error(usize),
+}
+
+/// Statistics of a search, reported periodically while searching and once it is finished.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub files_walked: u64,
+    pub files_searched: u64,
+    pub bytes_searched: u64,
+    pub binary_files_skipped: u64,
+}
+
+/// Statistics and errors reported by all threads of a search.
+#[derive(Default)]
+struct SearchProgress {
+    files_walked: AtomicU64,
+    files_searched: AtomicU64,
+    bytes_searched: AtomicU64,
+    binary_files_skipped: AtomicU64,
+    errors: Mutex<Vec<String>>,
+}
+
+impl SearchProgress {
+    fn stats(&self) -> SearchStats {
+        SearchStats {
+            files_walked: self.files_walked.load(atomic::Ordering::Relaxed),
+            files_searched: self.files_searched.load(atomic::Ordering::Relaxed),
+            bytes_searched: self.bytes_searched.load(atomic::Ordering::Relaxed),
+            binary_files_skipped: self.binary_files_skipped.load(atomic::Ordering::Relaxed),
+        }
+    }
+
+    fn add_walked(&self) {
+        self.files_walked.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    fn add_error(&self, error: String) {
+        self.errors.lock().expect("Errors poisoned").push(error);
+    }
+
+    fn take_errors(&self) -> Vec<String> {
+        std::mem::take(&mut self.errors.lock().expect("Errors poisoned"))
+    }
+}
+
+/// Matcher of the regex engine selected for a search.
+pub(crate) enum PatternMatcher {
+    RustRegex(RegexMatcher),
+    #[cfg(feature = "pcre2")]
+    Pcre2(grep::pcre2::RegexMatcher),
 }
 
-pub fn search(config: SearchConfig, tx: mpsc::Sender<Event>) {
+pub fn search(
+    config: SearchConfig,
+    matcher: PatternMatcher,
+    generation: usize,
+    control: Arc<SearchControl>,
+    tx: mpsc::SyncSender<Event>,
+) {
     std::thread::spawn(move || {
-        let path_searchers = config
-            .paths
-            .clone()
-            .into_iter()
+        let progress = Arc::new(SearchProgress::default());
+        let (finished_tx, finished_rx) = mpsc::channel::<()>();
+        let reporter = {
+            let progress = progress.clone();
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    finished_rx.recv_timeout(PROGRESS_INTERVAL)
+                {
+                    tx.send(Event::Progress(
+                        generation,
+                        progress.stats(),
+                        progress.take_errors(),
+                    ))
+                    .ok();
+                }
+            })
+        };
+
+        let finished = match matcher {
+            PatternMatcher::RustRegex(matcher) => search_paths(
+                config,
+                matcher,
+                generation,
+                control,
+                progress.clone(),
+                tx.clone(),
+            ),
+            #[cfg(feature = "pcre2")]
+            PatternMatcher::Pcre2(matcher) => search_paths(
+                config,
+                matcher,
+                generation,
+                control,
+                progress.clone(),
+                tx.clone(),
+            ),
+        };
+
+        drop(finished_tx);
+        reporter.join().ok();
+
+        tx.send(Event::Progress(
+            generation,
+            progress.stats(),
+            progress.take_errors(),
+        ))
+        .ok();
+        if finished {
+            tx.send(Event::SearchingFinished(generation)).ok();
+        } else {
+            tx.send(Event::Error(generation)).ok();
+        }
+    });
+}
+
+/// Returns `false` if any of the searcher threads panicked.
+fn search_paths<M>(
+    config: SearchConfig,
+    matcher: M,
+    generation: usize,
+    control: Arc<SearchControl>,
+    progress: Arc<SearchProgress>,
+    tx: mpsc::SyncSender<Event>,
+) -> bool
+where
+    M: Matcher + Clone + Send + Sync + 'static,
+{
+    if let Some(file_list) = config.file_list.clone() {
+        return search_file_list(
+            &file_list, config, matcher, generation, &control, &progress, tx,
+        );
+    }
+
+    let path_searchers = config
+        .paths
+        .clone()
+        .into_iter()
+        .map(|path| {
+            let config = config.clone();
+            let matcher = matcher.clone();
+            let control = control.clone();
+            let progress = progress.clone();
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                run(&path, config, matcher, generation, control, progress, tx)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    path_searchers
+        .into_iter()
+        .all(|searcher| searcher.join().is_ok())
+}
+
+/// Searches the given files on a pool of threads, bypassing the directory walker.
+/// Files are searched in the order of the sort key, if it is applied while walking.
+/// Returns `false` if any of the threads panicked.
+fn search_file_list<M>(
+    file_list: &[PathBuf],
+    config: SearchConfig,
+    matcher: M,
+    generation: usize,
+    control: &SearchControl,
+    progress: &SearchProgress,
+    tx: mpsc::SyncSender<Event>,
+) -> bool
+where
+    M: Matcher + Clone + Send + Sync + 'static,
+{
+    if let Some((order, reversed)) = config.sort_by.and_then(WalkOrder::of) {
+        let mut files = file_list
+            .iter()
+            .filter(|path| !is_filtered_out(path, &config))
+            .inspect(|_| progress.add_walked())
             .map(|path| {
-                let config = config.clone();
-                let tx = tx.clone();
-                std::thread::spawn(move || run(&path, config, tx))
+                let metadata = order
+                    .needs_metadata()
+                    .then(|| path.metadata().ok())
+                    .flatten();
+                (path.clone(), metadata)
             })
             .collect::<Vec<_>>();
+        order.sort(&mut files, reversed);
 
-        for searcher in path_searchers {
-            if searcher.join().is_err() {
-                tx.send(Event::Error).ok();
-                return;
-            }
-        }
+        // a panic of a searcher thread is reported by joining the thread running them
+        return std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    search_in_order(
+                        files.into_iter().map(|(path, _)| path),
+                        &config,
+                        &matcher,
+                        generation,
+                        control,
+                        progress,
+                        &tx,
+                    )
+                })
+                .join()
+                .is_ok()
+        });
+    }
 
-        tx.send(Event::SearchingFinished).ok();
-    });
+    let next_file = AtomicUsize::new(0);
+    let threads_count = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+
+    std::thread::scope(|scope| {
+        let workers = (0..threads_count)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut file_searcher = FileSearcher::new(&config);
+
+                    while !control.is_cancelled() {
+                        let index = next_file.fetch_add(1, atomic::Ordering::Relaxed);
+                        let Some(path) = file_list.get(index) else {
+                            break;
+                        };
+                        if is_filtered_out(path, &config) {
+                            continue;
+                        }
+                        progress.add_walked();
+                        file_searcher.search(path, &config, &matcher, progress, control, |entry| {
+                            tx.send(Event::NewEntry(generation, entry)).ok();
+                        });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers.into_iter().all(|worker| worker.join().is_ok())
+    })
 }
 
-fn run(path: &Path, config: SearchConfig, tx: mpsc::Sender<Event>) {
-    let grep_searcher = SearcherBuilder::new()
-        .binary_detection(BinaryDetection::quit(b'\x00'))
-        .line_terminator(LineTerminator::byte(b'\n'))
-        .line_number(true)
-        .multi_line(config.multi_line)
-        .build();
+/// Applies globs, file types and the maximum file size, which the walker applies to walked files.
+fn is_filtered_out(path: &Path, config: &SearchConfig) -> bool {
+    config.overrides.matched(path, false).is_ignore()
+        || config.types.matched(path, false).is_ignore()
+        || config.max_filesize.is_some_and(|max_filesize| {
+            path.metadata()
+                .is_ok_and(|metadata| metadata.len() > max_filesize)
+        })
+}
 
-    let mut regex_matcher_builder = RegexMatcherBuilder::new();
-    regex_matcher_builder
-        .case_insensitive(config.case_insensitive)
-        .case_smart(config.case_smart)
-        .word(config.word_regexp)
-        .fixed_strings(config.fixed_strings)
-        .multi_line(config.multi_line);
+/// Searcher of files reused by a single thread.
+#[derive(Clone)]
+struct FileSearcher {
+    grep_searcher: Searcher,
+    decompression: Option<DecompressionReaderBuilder>,
+}
 
-    // INFO: enable this for non-multiline pattern.
-    // HACK: without disabling this we will occur the NotAllowed("\n").
-    if !config.multi_line {
-        regex_matcher_builder.line_terminator(Some(b'\n'));
+impl FileSearcher {
+    fn new(config: &SearchConfig) -> Self {
+        let binary_detection = if config.search_binary {
+            BinaryDetection::convert(b'\x00')
+        } else {
+            BinaryDetection::quit(b'\x00')
+        };
+        let grep_searcher = SearcherBuilder::new()
+            .binary_detection(binary_detection)
+            .line_terminator(LineTerminator::byte(b'\n'))
+            .line_number(true)
+            .multi_line(config.multi_line)
+            .invert_match(config.invert_match)
+            .encoding(
+                config
+                    .encoding
+                    .and_then(|encoding| Encoding::new(encoding.name()).ok()),
+            )
+            .after_context(config.after_context)
+            .before_context(config.before_context)
+            .build();
+
+        Self {
+            grep_searcher,
+            decompression: config.search_zip.then(DecompressionReaderBuilder::new),
+        }
+    }
+
+    /// Searches the file and emits its matches. Matches found before the search pauses at
+    /// the results limits are emitted before pausing, so a file can be emitted in parts.
+    fn search<M: Matcher>(
+        &mut self,
+        path: &Path,
+        config: &SearchConfig,
+        matcher: &M,
+        progress: &SearchProgress,
+        control: &SearchControl,
+        mut emit: impl FnMut(FileEntry),
+    ) {
+        // files are not started while the search is paused
+        if !control.wait_for_budget() {
+            return;
+        }
+
+        let name = config.display_path(path);
+        let mut matches_in_entry = Vec::new();
+        let mut sr = MatchesSink::new(matcher, &mut matches_in_entry, config.max_count, control)
+            .on_pause(|matches| emit(FileEntry::new(name.clone(), matches)));
+        let searched = match (&config.pre, &self.decompression) {
+            (Some(pre), _) if file_reader::is_preprocessed(path, config) => {
+                file_reader::preprocess(path, pre)
+                    .and_then(|reader| self.grep_searcher.search_reader(matcher, reader, &mut sr))
+                    .map_err(|err| err.to_string())
+            }
+            // files not recognized as compressed are read as they are
+            (_, Some(decompression)) => match decompression.build(path) {
+                Ok(reader) => self
+                    .grep_searcher
+                    .search_reader(matcher, reader, &mut sr)
+                    .map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            },
+            _ => self
+                .grep_searcher
+                .search_path(matcher, path, &mut sr)
+                .map_err(|err| err.to_string()),
+        };
+
+        if let Err(err) = searched {
+            progress.add_error(format!("{name}: {err}"));
+        } else {
+            progress
+                .files_searched
+                .fetch_add(1, atomic::Ordering::Relaxed);
+            progress
+                .bytes_searched
+                .fetch_add(sr.byte_count(), atomic::Ordering::Relaxed);
+            // binary files are only searched when they are converted
+            if sr.is_binary() && !config.search_binary {
+                progress
+                    .binary_files_skipped
+                    .fetch_add(1, atomic::Ordering::Relaxed);
+            }
+        }
+        drop(sr);
+
+        if !matches_in_entry.is_empty() {
+            emit(FileEntry::new(name, matches_in_entry));
+        }
     }
-    let matcher = regex_matcher_builder
-        .build(&config.pattern)
-        .expect("Cannot build RegexMatcher");
+}
+
+fn run<M>(
+    path: &Path,
+    config: SearchConfig,
+    matcher: M,
+    generation: usize,
+    control: Arc<SearchControl>,
+    progress: Arc<SearchProgress>,
+    tx: mpsc::SyncSender<Event>,
+) where
+    M: Matcher + Clone + Send + Sync + 'static,
+{
+    let file_searcher = FileSearcher::new(&config);
 
     let mut builder = WalkBuilder::new(path);
     let walker = builder
         .overrides(config.overrides.clone())
         .types(config.types.clone())
         .hidden(!config.search_hidden)
-        .follow_links(config.follow_links);
+        .follow_links(config.follow_links)
+        .max_depth(config.max_depth)
+        .max_filesize(config.max_filesize)
+        .same_file_system(config.one_file_system)
+        .ignore(!config.no_ignore)
+        .git_ignore(!config.no_ignore && !config.no_ignore_vcs)
+        .git_global(!config.no_ignore && !config.no_ignore_vcs)
+        .git_exclude(!config.no_ignore && !config.no_ignore_vcs)
+        .parents(!config.no_ignore && !config.no_ignore_parent);
+    if !config.no_ignore {
+        walker.add_custom_ignore_filename(IGREP_IGNORE_FILENAME);
+    }
+    if !config.no_ignore_files {
+        for ignore_file in &config.ignore_files {
+            // files are validated when creating the config
+            walker.add_ignore(ignore_file);
+        }
+    }
 
-    // if no sort is specified the faster parallel search is used
-    match config.sort_by {
+    // if no sort is applied while walking, files are searched as soon as they are walked
+    match config.sort_by.and_then(WalkOrder::of) {
         None => {
             let walk_parallel = walker.build_parallel();
+            let config = &config;
 
             walk_parallel.run(move || {
                 let tx = tx.clone();
                 let matcher = matcher.clone();
-                let mut grep_searcher = grep_searcher.clone();
+                let mut file_searcher = file_searcher.clone();
+                let control = control.clone();
+                let progress = progress.clone();
 
                 Box::new(move |result| {
+                    if control.is_cancelled() {
+                        return ignore::WalkState::Quit;
+                    }
+
                     let dir_entry = match result {
                         Ok(entry) => {
                             if !entry.file_type().is_some_and(|ft| ft.is_file()) {
@@ -89,91 +446,544 @@ fn run(path: &Path, config: SearchConfig, tx: mpsc::Sender<Event>) {
                             }
                             entry
                         }
-                        Err(_) => return ignore::WalkState::Continue,
+                        Err(err) => {
+                            progress.add_error(err.to_string());
+                            return ignore::WalkState::Continue;
+                        }
                     };
-                    let mut matches_in_entry = Vec::new();
-                    let sr = MatchesSink::new(&matcher, &mut matches_in_entry);
-                    grep_searcher
-                        .search_path(&matcher, dir_entry.path(), sr)
-                        .ok();
-
-                    if !matches_in_entry.is_empty() {
-                        tx.send(Event::NewEntry(FileEntry::new(
-                            dir_entry.path().to_string_lossy().into_owned(),
-                            matches_in_entry,
-                        )))
-                        .ok();
-                    }
+                    progress.add_walked();
+                    file_searcher.search(
+                        dir_entry.path(),
+                        config,
+                        &matcher,
+                        &progress,
+                        &control,
+                        |entry| {
+                            tx.send(Event::NewEntry(generation, entry)).ok();
+                        },
+                    );
 
                     ignore::WalkState::Continue
                 })
             });
         }
-        Some(key) => {
-            let walk_sorted =
-                match key {
-                    SortKey::Path => walker.sort_by_file_name(|a, b| a.cmp(b)),
-                    SortKey::PathReversed => walker.sort_by_file_name(|a, b| b.cmp(a)),
-                    SortKey::Modified => walker
-                        .sort_by_file_path(|a, b| compare_metadata(a, b, |m| m.modified(), false)),
-                    SortKey::ModifiedReversed => walker
-                        .sort_by_file_path(|a, b| compare_metadata(a, b, |m| m.modified(), true)),
-                    SortKey::Created => walker
-                        .sort_by_file_path(|a, b| compare_metadata(a, b, |m| m.created(), false)),
-                    SortKey::CreatedReversed => walker
-                        .sort_by_file_path(|a, b| compare_metadata(a, b, |m| m.created(), true)),
-                    SortKey::Accessed => walker
-                        .sort_by_file_path(|a, b| compare_metadata(a, b, |m| m.accessed(), false)),
-                    SortKey::AccessedReversed => walker
-                        .sort_by_file_path(|a, b| compare_metadata(a, b, |m| m.accessed(), true)),
-                };
-
-            for result in walk_sorted.build() {
-                let tx = tx.clone();
-                let matcher = matcher.clone();
-                let mut grep_searcher = grep_searcher.clone();
+        Some((order, reversed)) => {
+            // walking is cheap compared to searching, so all files are walked in parallel
+            // and sorted before they are searched
+            let walked = Mutex::new(Vec::new());
+            walker.build_parallel().run(|| {
+                Box::new(|result| {
+                    if control.is_cancelled() {
+                        return ignore::WalkState::Quit;
+                    }
 
-                let dir_entry = match result {
-                    Ok(entry) => {
-                        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
-                            continue;
+                    match result {
+                        Ok(entry) if entry.file_type().is_some_and(|ft| ft.is_file()) => {
+                            progress.add_walked();
+                            let metadata = order
+                                .needs_metadata()
+                                .then(|| entry.metadata().ok())
+                                .flatten();
+                            walked
+                                .lock()
+                                .expect("Walked files poisoned")
+                                .push((entry.into_path(), metadata));
                         }
-                        entry
+                        Ok(_) => (),
+                        Err(err) => progress.add_error(err.to_string()),
                     }
-                    Err(_) => continue,
+
+                    ignore::WalkState::Continue
+                })
+            });
+
+            let mut walked = walked.into_inner().expect("Walked files poisoned");
+            order.sort(&mut walked, reversed);
+            search_in_order(
+                walked.into_iter().map(|(path, _)| path),
+                &config,
+                &matcher,
+                generation,
+                &control,
+                &progress,
+                &tx,
+            );
+        }
+    }
+}
+
+/// Order of files for sort keys which are applied while walking directories.
+#[derive(Clone, Copy)]
+enum WalkOrder {
+    Path,
+    Natural,
+    Modified,
+    Created,
+    Accessed,
+    Size,
+}
+
+impl WalkOrder {
+    /// Returns the order and whether it is reversed, `None` for keys applied to results.
+    fn of(key: SortKey) -> Option<(Self, bool)> {
+        let order = match key {
+            SortKey::Path | SortKey::PathReversed => WalkOrder::Path,
+            SortKey::Natural | SortKey::NaturalReversed => WalkOrder::Natural,
+            SortKey::Modified | SortKey::ModifiedReversed => WalkOrder::Modified,
+            SortKey::Created | SortKey::CreatedReversed => WalkOrder::Created,
+            SortKey::Accessed | SortKey::AccessedReversed => WalkOrder::Accessed,
+            SortKey::Size | SortKey::SizeReversed => WalkOrder::Size,
+            SortKey::Matches
+            | SortKey::MatchesReversed
+            | SortKey::Depth
+            | SortKey::DepthReversed => return None,
+        };
+        Some((order, key.is_reversed()))
+    }
+
+    fn needs_metadata(self) -> bool {
+        !matches!(self, WalkOrder::Path | WalkOrder::Natural)
+    }
+
+    /// Sorts files with their metadata, files equal in the order are sorted by path.
+    fn sort(self, files: &mut [(PathBuf, Option<Metadata>)], reversed: bool) {
+        files.sort_by(|(lhs, lhs_metadata), (rhs, rhs_metadata)| {
+            self.compare(
+                lhs,
+                lhs_metadata.as_ref(),
+                rhs,
+                rhs_metadata.as_ref(),
+                reversed,
+            )
+            .then_with(|| lhs.cmp(rhs))
+        });
+    }
+
+    fn compare(
+        self,
+        lhs: &Path,
+        lhs_metadata: Option<&Metadata>,
+        rhs: &Path,
+        rhs_metadata: Option<&Metadata>,
+        reversed: bool,
+    ) -> Ordering {
+        match self {
+            WalkOrder::Path if reversed => rhs.cmp(lhs),
+            WalkOrder::Path => lhs.cmp(rhs),
+            WalkOrder::Natural if reversed => natural_path_cmp(rhs, lhs),
+            WalkOrder::Natural => natural_path_cmp(lhs, rhs),
+            WalkOrder::Modified => {
+                compare_metadata(lhs_metadata, rhs_metadata, Metadata::modified, reversed)
+            }
+            WalkOrder::Created => {
+                compare_metadata(lhs_metadata, rhs_metadata, Metadata::created, reversed)
+            }
+            WalkOrder::Accessed => {
+                compare_metadata(lhs_metadata, rhs_metadata, Metadata::accessed, reversed)
+            }
+            WalkOrder::Size => {
+                compare_metadata(lhs_metadata, rhs_metadata, |m| Ok(m.len()), reversed)
+            }
+        }
+    }
+}
+
+/// Reorders entries of files searched in parallel back into the order they were walked in.
+#[derive(Default)]
+struct OrderedEntries {
+    next_index: usize,
+    /// Entries of files which cannot be emitted yet, and whether their search is finished.
+    pending: HashMap<usize, (Vec<FileEntry>, bool)>,
+}
+
+impl OrderedEntries {
+    /// Adds an entry of the file at `index` and emits entries as soon as all files preceding
+    /// them are searched. A file paused by the results limits adds entries before it is finished.
+    fn add(
+        &mut self,
+        index: usize,
+        entry: Option<FileEntry>,
+        finished: bool,
+        mut emit: impl FnMut(FileEntry),
+    ) {
+        let (entries, is_finished) = self.pending.entry(index).or_default();
+        entries.extend(entry);
+        *is_finished = finished;
+
+        while let Some((entries, finished)) = self.pending.get_mut(&self.next_index) {
+            entries.drain(..).for_each(&mut emit);
+            if !*finished {
+                break;
+            }
+            self.pending.remove(&self.next_index);
+            self.next_index += 1;
+        }
+    }
+}
+
+/// Searches files on a pool of threads, sending their entries in the order of `paths`.
+fn search_in_order<M>(
+    paths: impl Iterator<Item = PathBuf>,
+    config: &SearchConfig,
+    matcher: &M,
+    generation: usize,
+    control: &SearchControl,
+    progress: &SearchProgress,
+    tx: &mpsc::SyncSender<Event>,
+) where
+    M: Matcher + Sync,
+{
+    let threads_count = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let (paths_tx, paths_rx) = mpsc::sync_channel::<(usize, PathBuf)>(threads_count * 4);
+    let paths_rx = Mutex::new(paths_rx);
+    let ordered_entries = Mutex::new(OrderedEntries::default());
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads_count {
+            scope.spawn(|| {
+                let mut file_searcher = FileSearcher::new(config);
+                // entries are sent under the lock to keep them in order
+                let add_entry = |index, entry, finished| {
+                    ordered_entries
+                        .lock()
+                        .expect("Ordered entries poisoned")
+                        .add(index, entry, finished, |entry| {
+                            tx.send(Event::NewEntry(generation, entry)).ok();
+                        });
                 };
-                let mut matches_in_entry = Vec::new();
-                let sr = MatchesSink::new(&matcher, &mut matches_in_entry);
-                grep_searcher
-                    .search_path(&matcher, dir_entry.path(), sr)
-                    .ok();
 
-                if !matches_in_entry.is_empty() {
-                    tx.send(Event::NewEntry(FileEntry::new(
-                        dir_entry.path().to_string_lossy().into_owned(),
-                        matches_in_entry,
-                    )))
-                    .ok();
+                loop {
+                    let next_path = paths_rx.lock().expect("Paths receiver poisoned").recv();
+                    let Ok((index, path)) = next_path else {
+                        break;
+                    };
+                    // paths of a cancelled search are drained, so that the walk is not blocked
+                    if !control.is_cancelled() {
+                        file_searcher.search(&path, config, matcher, progress, control, |entry| {
+                            add_entry(index, Some(entry), false)
+                        });
+                    }
+                    add_entry(index, None, true);
                 }
+            });
+        }
 
-                continue;
+        for (index, path) in paths.enumerate() {
+            if control.is_cancelled() || paths_tx.send((index, path)).is_err() {
+                break;
             }
         }
+        drop(paths_tx);
+    });
+}
+
+pub(crate) fn build_matcher(config: &SearchConfig) -> Result<PatternMatcher> {
+    match config.engine {
+        #[cfg(feature = "pcre2")]
+        Engine::Default => Ok(PatternMatcher::RustRegex(build_rust_regex_matcher(
+            config,
+            &config.patterns,
+        )?)),
+        // without PCRE2 the automatic choice is left with the default engine
+        #[cfg(not(feature = "pcre2"))]
+        Engine::Default | Engine::Auto => Ok(PatternMatcher::RustRegex(build_rust_regex_matcher(
+            config,
+            &config.patterns,
+        )?)),
+        #[cfg(feature = "pcre2")]
+        Engine::Pcre2 => Ok(PatternMatcher::Pcre2(build_pcre2_matcher(
+            config,
+            &config.patterns,
+        )?)),
+        #[cfg(feature = "pcre2")]
+        Engine::Auto => match build_rust_regex_matcher(config, &config.patterns) {
+            Ok(matcher) => Ok(PatternMatcher::RustRegex(matcher)),
+            // patterns with look-around or backreferences are rejected by the default engine
+            Err(_) => Ok(PatternMatcher::Pcre2(build_pcre2_matcher(
+                config,
+                &config.patterns,
+            )?)),
+        },
+        #[cfg(not(feature = "pcre2"))]
+        Engine::Pcre2 => {
+            anyhow::bail!("PCRE2 is not available in this build of igrep")
+        }
+    }
+}
+
+fn build_rust_regex_matcher(
+    config: &SearchConfig,
+    patterns: &[String],
+) -> Result<RegexMatcher, grep::regex::Error> {
+    let mut regex_matcher_builder = RegexMatcherBuilder::new();
+    regex_matcher_builder
+        .case_insensitive(config.case_insensitive)
+        .case_smart(config.case_smart)
+        .word(config.word_regexp)
+        // several fixed strings are escaped when grouped
+        .fixed_strings(config.fixed_strings && patterns.len() == 1)
+        .multi_line(config.multi_line);
+
+    // INFO: enable this for non-multiline pattern.
+    // HACK: without disabling this we will occur the NotAllowed("\n").
+    if !config.multi_line {
+        regex_matcher_builder.line_terminator(Some(b'\n'));
     }
+    regex_matcher_builder.build_many(&group_patterns(patterns, config.fixed_strings))
 }
 
-fn compare_metadata<F, T>(lhs: &Path, rhs: &Path, extractor: F, reversed: bool) -> Ordering
+#[cfg(feature = "pcre2")]
+fn build_pcre2_matcher(
+    config: &SearchConfig,
+    patterns: &[String],
+) -> Result<grep::pcre2::RegexMatcher, grep::pcre2::Error> {
+    let mut pcre2_matcher_builder = grep::pcre2::RegexMatcherBuilder::new();
+    pcre2_matcher_builder
+        .caseless(config.case_insensitive)
+        .case_smart(config.case_smart)
+        .word(config.word_regexp)
+        // several fixed strings are escaped when grouped
+        .fixed_strings(config.fixed_strings && patterns.len() == 1)
+        .multi_line(config.multi_line)
+        .utf(true)
+        .ucp(true)
+        .jit_if_available(true);
+    pcre2_matcher_builder.build_many(&group_patterns(patterns, config.fixed_strings))
+}
+
+/// Files whose metadata could not be read, e.g. deleted while walking, are ordered last.
+fn compare_metadata<F, T>(
+    lhs: Option<&Metadata>,
+    rhs: Option<&Metadata>,
+    extractor: F,
+    reversed: bool,
+) -> Ordering
 where
-    F: Fn(&std::fs::Metadata) -> std::io::Result<T>,
+    F: Fn(&Metadata) -> std::io::Result<T>,
     T: Ord,
 {
-    let metadata_lhs = lhs.metadata().expect("cannot get metadata from file");
-    let metadata_rhs = rhs.metadata().expect("cannot get metadata from file");
-    let time_lhs = extractor(&metadata_lhs).expect("cannot get time of file");
-    let time_rhs = extractor(&metadata_rhs).expect("cannot get time of file");
-    if reversed {
-        time_rhs.cmp(&time_lhs)
-    } else {
-        time_lhs.cmp(&time_rhs)
+    let value_lhs = lhs.and_then(|metadata| extractor(metadata).ok());
+    let value_rhs = rhs.and_then(|metadata| extractor(metadata).ok());
+    match (value_lhs, value_rhs) {
+        (Some(value_lhs), Some(value_rhs)) if reversed => value_rhs.cmp(&value_lhs),
+        (Some(value_lhs), Some(value_rhs)) => value_lhs.cmp(&value_rhs),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Compares paths component by component, with names in the natural order.
+fn natural_path_cmp(lhs: &Path, rhs: &Path) -> Ordering {
+    lhs.components()
+        .zip_longest(rhs.components())
+        .map(|components| match components {
+            EitherOrBoth::Both(lhs, rhs) => natural_cmp(
+                &lhs.as_os_str().to_string_lossy(),
+                &rhs.as_os_str().to_string_lossy(),
+            ),
+            EitherOrBoth::Left(_) => Ordering::Greater,
+            EitherOrBoth::Right(_) => Ordering::Less,
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Compares names so that numbers in them are ordered by value, e.g. "file2" < "file10".
+fn natural_cmp(lhs: &str, rhs: &str) -> Ordering {
+    let (mut lhs_rest, mut rhs_rest) = (lhs, rhs);
+    loop {
+        let ordering = match (lhs_rest.chars().next(), rhs_rest.chars().next()) {
+            (None, None) => return lhs.cmp(rhs),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let (lhs_number, lhs_tail) = split_number(lhs_rest);
+                let (rhs_number, rhs_tail) = split_number(rhs_rest);
+                (lhs_rest, rhs_rest) = (lhs_tail, rhs_tail);
+                lhs_number
+                    .len()
+                    .cmp(&rhs_number.len())
+                    .then_with(|| lhs_number.cmp(rhs_number))
+            }
+            (Some(l), Some(r)) => {
+                (lhs_rest, rhs_rest) = (&lhs_rest[l.len_utf8()..], &rhs_rest[r.len_utf8()..]);
+                l.cmp(&r)
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Splits leading digits from the text, returns them without leading zeros.
+fn split_number(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    (text[..end].trim_start_matches('0'), &text[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::SortKeyArg;
+    use crate::ig::{file_entry::EntryType, grep_match::GrepMatch};
+
+    fn add(ordered_entries: &mut OrderedEntries, index: usize, name: Option<&str>) -> Vec<String> {
+        add_part(ordered_entries, index, name, true)
+    }
+
+    fn add_part(
+        ordered_entries: &mut OrderedEntries,
+        index: usize,
+        name: Option<&str>,
+        finished: bool,
+    ) -> Vec<String> {
+        let entry = name
+            .map(|name| FileEntry::new(name.into(), vec![GrepMatch::new(1, "m".into(), vec![])]));
+        let mut emitted = Vec::new();
+        ordered_entries.add(index, entry, finished, |entry| {
+            if let Some(EntryType::Header(name)) = entry.get_entries().first() {
+                emitted.push(name.clone());
+            }
+        });
+        emitted
+    }
+
+    #[test]
+    fn entries_are_emitted_in_order() {
+        let mut ordered_entries = OrderedEntries::default();
+        assert!(add(&mut ordered_entries, 2, Some("c")).is_empty());
+        assert!(add(&mut ordered_entries, 1, None).is_empty());
+        assert_eq!(add(&mut ordered_entries, 0, Some("a")), ["a", "c"]);
+        assert_eq!(add(&mut ordered_entries, 3, Some("d")), ["d"]);
+    }
+
+    #[test]
+    fn parts_of_paused_files_are_emitted_in_order() {
+        let mut ordered_entries = OrderedEntries::default();
+        assert!(add_part(&mut ordered_entries, 1, Some("b1"), false).is_empty());
+        assert_eq!(add_part(&mut ordered_entries, 0, Some("a1"), false), ["a1"]);
+        assert_eq!(add(&mut ordered_entries, 0, Some("a2")), ["a2", "b1"]);
+        assert_eq!(add(&mut ordered_entries, 1, None), Vec::<String>::new());
+        assert_eq!(add(&mut ordered_entries, 2, Some("c")), ["c"]);
+    }
+
+    #[test_case::test_case("file2", "file10" => Ordering::Less)]
+    #[test_case::test_case("file10", "file9" => Ordering::Greater)]
+    #[test_case::test_case("a1b2", "a1b10" => Ordering::Less)]
+    #[test_case::test_case("file01", "file1" => Ordering::Less; "leading zeros")]
+    #[test_case::test_case("file", "file1" => Ordering::Less)]
+    #[test_case::test_case("b", "a10" => Ordering::Greater)]
+    fn natural_order(lhs: &str, rhs: &str) -> Ordering {
+        natural_cmp(lhs, rhs)
+    }
+
+    #[test_case::test_case("dir2/b", "dir10/a" => Ordering::Less)]
+    #[test_case::test_case("dir/file10", "dir/file9" => Ordering::Greater)]
+    #[test_case::test_case("dir", "dir/file" => Ordering::Less; "parent first")]
+    fn natural_path_order(lhs: &str, rhs: &str) -> Ordering {
+        natural_path_cmp(Path::new(lhs), Path::new(rhs))
+    }
+
+    #[test]
+    fn missing_metadata_is_ordered_last() {
+        let metadata = Path::new(".").metadata().ok();
+        for reversed in [false, true] {
+            let ordering = compare_metadata(metadata.as_ref(), None, |m| Ok(m.len()), reversed);
+            assert_eq!(ordering, Ordering::Less);
+        }
+        assert_eq!(
+            compare_metadata(None, None, Metadata::modified, false),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn auto_engine_uses_default_engine_for_supported_patterns() {
+        let config = SearchConfig::from(vec!["lorem".into()], vec![])
+            .unwrap()
+            .engine(Engine::Auto)
+            .unwrap();
+        assert!(matches!(
+            build_matcher(&config),
+            Ok(PatternMatcher::RustRegex(_))
+        ));
+    }
+
+    #[test_case::test_case(None ; "walked in parallel")]
+    #[test_case::test_case(Some(SortKey::Path) ; "sorted")]
+    fn paused_files_resume_where_they_stopped(sort_by: Option<SortKey>) {
+        let dir = std::env::temp_dir().join(format!(
+            "igrep-paused-{}-{}",
+            std::process::id(),
+            sort_by.is_some()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a", "b"] {
+            std::fs::write(dir.join(name), "lorem\n".repeat(5)).unwrap();
+        }
+        let mut config = SearchConfig::from(vec!["lorem".into()], vec![dir.clone()]).unwrap();
+        config.max_results = Some(3);
+        config.sort_by = sort_by;
+        let matcher = build_matcher(&config).unwrap();
+        let control = Arc::new(SearchControl::new(config.max_results, None));
+        let (tx, rx) = mpsc::sync_channel(16);
+
+        search(config, matcher, 0, control.clone(), tx);
+        let mut matches_count = 0;
+        loop {
+            match rx.recv_timeout(Duration::from_millis(10)) {
+                Ok(Event::NewEntry(_, entry)) => matches_count += entry.get_matches_count(),
+                Ok(Event::SearchingFinished(_)) => break,
+                Ok(_) => (),
+                Err(_) if control.is_paused() => control.continue_search(),
+                Err(_) => (),
+            }
+        }
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(matches_count, 10);
+    }
+
+    #[test]
+    fn file_list_is_sorted_and_filtered_by_size() {
+        let dir = std::env::temp_dir().join(format!("igrep-file-list-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_list = ["file12", "file2", "file1", "large"]
+            .map(|name| dir.join(name))
+            .to_vec();
+        for path in &file_list {
+            std::fs::write(path, "lorem\n").unwrap();
+        }
+        std::fs::write(dir.join("large"), "lorem\n".repeat(100)).unwrap();
+        let config = SearchConfig::from(vec!["lorem".into()], vec![])
+            .unwrap()
+            .file_list(Some(file_list))
+            .max_filesize(Some(100))
+            .sort_by(Some(SortKeyArg::Natural), None)
+            .unwrap();
+        let matcher = build_matcher(&config).unwrap();
+        let (tx, rx) = mpsc::sync_channel(16);
+
+        search(config, matcher, 0, Arc::default(), tx);
+        let mut names = Vec::new();
+        for event in rx {
+            match event {
+                Event::NewEntry(_, entry) => {
+                    if let Some(EntryType::Header(name)) = entry.get_entries().first() {
+                        names.push(Path::new(name).file_name().unwrap().to_owned());
+                    }
+                }
+                Event::SearchingFinished(_) => break,
+                _ => (),
+            }
+        }
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(names, ["file1", "file2", "file12"]);
     }
 }
@@ -1,40 +1,151 @@
 use grep::{
-    matcher::Matcher,
-    searcher::{Searcher, Sink, SinkMatch},
+    matcher::{Captures, Matcher},
+    searcher::{Searcher, Sink, SinkContext, SinkFinish, SinkMatch},
 };
 
-use super::grep_match::GrepMatch;
+use super::{grep_match::GrepMatch, search_control::SearchControl};
 
 pub(crate) struct MatchesSink<'a, M>
 where
     M: Matcher,
 {
-    matcher: M,
+    matcher: &'a M,
+    /// Capture groups of the patterns, empty when searching for a single pattern.
+    pattern_groups: Vec<usize>,
     matches_in_entry: &'a mut Vec<GrepMatch>,
+    max_count: Option<u64>,
+    control: &'a SearchControl,
+    on_pause: Option<Box<dyn FnMut(Vec<GrepMatch>) + 'a>>,
+    count: u64,
+    byte_count: u64,
+    binary: bool,
 }
 
 impl<'a, M> MatchesSink<'a, M>
 where
     M: Matcher,
 {
-    pub(crate) fn new(matcher: M, matches_in_entry: &'a mut Vec<GrepMatch>) -> Self {
+    pub(crate) fn new(
+        matcher: &'a M,
+        matches_in_entry: &'a mut Vec<GrepMatch>,
+        max_count: Option<u64>,
+        control: &'a SearchControl,
+    ) -> Self {
+        let pattern_groups = (0..)
+            .map_while(|index| matcher.capture_index(&pattern_group(index)))
+            .collect();
         Self {
             matcher,
+            pattern_groups,
             matches_in_entry,
+            max_count,
+            control,
+            on_pause: None,
+            count: 0,
+            byte_count: 0,
+            binary: false,
         }
     }
+
+    /// Hands over matches found so far before pausing at the results limits,
+    /// so that they are shown while the search is paused.
+    pub(crate) fn on_pause(mut self, on_pause: impl FnMut(Vec<GrepMatch>) + 'a) -> Self {
+        self.on_pause = Some(Box::new(on_pause));
+        self
+    }
+
+    /// Number of bytes searched, known once the search is finished.
+    pub(crate) fn byte_count(&self) -> u64 {
+        self.byte_count
+    }
+
+    /// Whether binary data was found in the searched file.
+    pub(crate) fn is_binary(&self) -> bool {
+        self.binary
+    }
+
+    /// Accounts matches in the results limits, pausing the search while they are reached.
+    /// Returns `false` if the search is cancelled.
+    fn spend(&mut self, results: usize, memory: u64) -> bool {
+        while !self.control.spend(results, memory) {
+            if self.control.is_cancelled() {
+                return false;
+            }
+            if let Some(on_pause) = &mut self.on_pause {
+                if !self.matches_in_entry.is_empty() {
+                    on_pause(std::mem::take(self.matches_in_entry));
+                }
+            }
+            if !self.control.wait_for_budget() {
+                return false;
+            }
+        }
+        true
+    }
 }
 
-fn split_by_lines(line_number: u64, text: &str, offsets: Vec<(usize, usize)>) -> Vec<GrepMatch> {
+/// Name of the capture group wrapping the pattern at `index` among several patterns.
+fn pattern_group(index: usize) -> String {
+    format!("igrep_pattern{index}")
+}
+
+/// Wraps each of several patterns in a named group, so that the pattern of every match is
+/// known from the captures of the combined matcher. Fixed strings are escaped here, as the
+/// groups must not be, and a single pattern is left as it is.
+pub(crate) fn group_patterns(patterns: &[String], fixed_strings: bool) -> Vec<String> {
+    if patterns.len() < 2 {
+        return patterns.to_vec();
+    }
+
+    patterns
+        .iter()
+        .enumerate()
+        .map(|(index, pattern)| {
+            let pattern = if fixed_strings {
+                escape(pattern)
+            } else {
+                pattern.clone()
+            };
+            format!("(?<{}>{pattern})", pattern_group(index))
+        })
+        .collect()
+}
+
+/// Escapes characters with a special meaning in both regex engines.
+fn escape(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn split_by_lines(
+    line_number: u64,
+    text: &str,
+    offsets: Vec<(usize, usize, usize)>,
+) -> Vec<GrepMatch> {
     let mut matches = Vec::new();
     if !text.trim().contains('\n') {
         matches.push(GrepMatch::new(line_number, text.into(), offsets));
+    } else if offsets.is_empty() {
+        // inverted matches have nothing to highlight
+        for (idx, el) in text.lines().enumerate() {
+            matches.push(GrepMatch::new(
+                line_number + idx as u64,
+                el.to_string(),
+                vec![],
+            ));
+        }
     } else {
         // handles multiline searches
-        let Some(&(first_offset_start, _)) = offsets.first() else {
+        let Some(&(first_offset_start, _, pattern_index)) = offsets.first() else {
             return Vec::new();
         };
-        let Some((_, mut last_offset_end)) = offsets.last() else {
+        let Some(&(_, mut last_offset_end, _)) = offsets.last() else {
             return Vec::new();
         };
         for (idx, el) in text.lines().enumerate() {
@@ -42,7 +153,11 @@ fn split_by_lines(line_number: u64, text: &str, offsets: Vec<(usize, usize)>) ->
             matches.push(GrepMatch::new(
                 line_number + idx as u64,
                 el.to_string(),
-                vec![(start, std::cmp::min(el.len(), last_offset_end))],
+                vec![(
+                    start,
+                    std::cmp::min(el.len(), last_offset_end),
+                    pattern_index,
+                )],
             ));
             last_offset_end = last_offset_end.saturating_sub(el.len() + 1);
         }
@@ -61,21 +176,71 @@ where
         let line_number = sink_match
             .line_number()
             .ok_or(std::io::ErrorKind::InvalidData)?;
-        let text =
-            std::str::from_utf8(sink_match.bytes()).map_err(|_| std::io::ErrorKind::InvalidData)?;
+        // offsets are looked up in the lossy text, so they stay valid after replacing invalid UTF-8
+        let text = String::from_utf8_lossy(sink_match.bytes());
 
         let mut offsets = vec![];
-        self.matcher
-            .find_iter(sink_match.bytes(), |m| {
-                offsets.push((m.start(), m.end()));
-                true
-            })
-            .ok();
+        if self.pattern_groups.is_empty() {
+            self.matcher
+                .find_iter(text.as_bytes(), |m| {
+                    offsets.push((m.start(), m.end(), 0));
+                    true
+                })
+                .ok();
+        } else if let Ok(mut captures) = self.matcher.new_captures() {
+            self.matcher
+                .captures_iter(text.as_bytes(), &mut captures, |captures| {
+                    if let Some(m) = captures.get(0) {
+                        let pattern_index = self
+                            .pattern_groups
+                            .iter()
+                            .position(|&group| captures.get(group).is_some())
+                            .unwrap_or_default();
+                        offsets.push((m.start(), m.end(), pattern_index));
+                    }
+                    true
+                })
+                .ok();
+        }
+
+        let matches = split_by_lines(line_number, &text, offsets);
+        let memory = matches.iter().map(GrepMatch::memory_size).sum();
+        if !self.spend(matches.len(), memory) {
+            return Ok(false);
+        }
+        self.matches_in_entry.extend(matches);
+
+        // stop searching the file once it has enough matches
+        self.count += 1;
+        Ok(self
+            .max_count
+            .is_none_or(|max_count| self.count < max_count))
+    }
+
+    fn context(
+        &mut self,
+        _: &Searcher,
+        sink_context: &SinkContext,
+    ) -> Result<bool, std::io::Error> {
+        let line_number = sink_context
+            .line_number()
+            .ok_or(std::io::ErrorKind::InvalidData)?;
+        let text = String::from_utf8_lossy(sink_context.bytes());
 
-        *self.matches_in_entry = split_by_lines(line_number, text, offsets);
+        let context = GrepMatch::context(line_number, text.into_owned());
+        if !self.spend(0, context.memory_size()) {
+            return Ok(false);
+        }
+        self.matches_in_entry.push(context);
 
         Ok(true)
     }
+
+    fn finish(&mut self, _: &Searcher, finish: &SinkFinish) -> Result<(), std::io::Error> {
+        self.byte_count = finish.byte_count();
+        self.binary = finish.binary_byte_offset().is_some();
+        Ok(())
+    }
 }
 
 // TESTS:
@@ -90,40 +255,236 @@ mod tests {
     #[test]
     fn single_line_single_match() {
         let text = "lorem ipsum dolor sit amet\n";
-        let matches = split_by_lines(0, text, vec![(5, 9)]);
+        let matches = split_by_lines(0, text, vec![(5, 9, 0)]);
 
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].text, text);
-        assert_eq!(matches[0].match_offsets, &[(5, 9)]);
+        assert_eq!(matches[0].match_offsets, &[(5, 9, 0)]);
     }
 
     #[test]
     fn single_line_multiple_matches() {
         let text = "lorem ipsum dolor sit amet\n";
-        let matches = split_by_lines(0, text, vec![(0, 4), (8, 12)]);
+        let matches = split_by_lines(0, text, vec![(0, 4, 0), (8, 12, 1)]);
 
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].text, text);
-        assert_eq!(matches[0].match_offsets, &[(0, 4), (8, 12)]);
+        assert_eq!(matches[0].match_offsets, &[(0, 4, 0), (8, 12, 1)]);
+    }
+
+    fn search(text: &str, pattern: &str, before: usize, after: usize) -> Vec<GrepMatch> {
+        search_many(text, &[pattern], before, after)
+    }
+
+    fn search_many(text: &str, patterns: &[&str], before: usize, after: usize) -> Vec<GrepMatch> {
+        search_with(
+            grep::regex::RegexMatcherBuilder::new(),
+            text,
+            patterns,
+            before,
+            after,
+        )
+    }
+
+    fn search_with(
+        builder: grep::regex::RegexMatcherBuilder,
+        text: &str,
+        patterns: &[&str],
+        before: usize,
+        after: usize,
+    ) -> Vec<GrepMatch> {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let matcher = builder
+            .build_many(&group_patterns(&patterns, false))
+            .unwrap();
+        let mut matches = Vec::new();
+        grep::searcher::SearcherBuilder::new()
+            .line_number(true)
+            .before_context(before)
+            .after_context(after)
+            .build()
+            .search_slice(
+                &matcher,
+                text.as_bytes(),
+                MatchesSink::new(&matcher, &mut matches, None, &SearchControl::default()),
+            )
+            .unwrap();
+        matches
+    }
+
+    fn search_inverted(text: &str, pattern: &str) -> Vec<GrepMatch> {
+        let matcher = grep::regex::RegexMatcher::new(pattern).unwrap();
+        let mut matches = Vec::new();
+        grep::searcher::SearcherBuilder::new()
+            .line_number(true)
+            .invert_match(true)
+            .build()
+            .search_slice(
+                &matcher,
+                text.as_bytes(),
+                MatchesSink::new(&matcher, &mut matches, None, &SearchControl::default()),
+            )
+            .unwrap();
+        matches
+    }
+
+    #[test]
+    fn all_matches_in_file() {
+        let matches = search("lorem\nipsum\nlorem\n", "lorem", 0, 0);
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].line_number, 1);
+        assert_eq!(matches[1].line_number, 3);
+    }
+
+    #[test]
+    fn matches_record_pattern() {
+        let matches = search_many("foo bar baz\n", &["baz", "foo"], 0, 0);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].match_offsets, &[(0, 3, 1), (8, 11, 0)]);
+    }
+
+    #[test]
+    fn pattern_is_told_by_combined_matcher() {
+        // smart case looks at all patterns, the uppercase one makes both case sensitive
+        let mut builder = grep::regex::RegexMatcherBuilder::new();
+        builder.case_smart(true);
+        let matches = search_with(builder, "FOO foo\n", &["foo", "FOO"], 0, 0);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].match_offsets, &[(0, 3, 1), (4, 7, 0)]);
+    }
+
+    #[test]
+    fn fixed_strings_are_escaped_in_groups() {
+        let patterns = ["a.b".to_string(), "(c)".to_string()];
+        let matcher = grep::regex::RegexMatcherBuilder::new()
+            .build_many(&group_patterns(&patterns, true))
+            .unwrap();
+
+        assert!(matcher.is_match(b"(c)").unwrap());
+        assert!(!matcher.is_match(b"axb c").unwrap());
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let mut matches = Vec::new();
+        let matcher = grep::regex::RegexMatcher::new("caf").unwrap();
+        grep::searcher::SearcherBuilder::new()
+            .line_number(true)
+            .build()
+            .search_slice(
+                &matcher,
+                b"caf\xe9 caf\n",
+                MatchesSink::new(&matcher, &mut matches, None, &SearchControl::default()),
+            )
+            .unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].text, "caf\u{fffd} caf\n");
+        assert_eq!(matches[0].match_offsets, &[(0, 3, 0), (7, 10, 0)]);
+    }
+
+    #[test]
+    fn max_count() {
+        let mut matches = Vec::new();
+        let matcher = grep::regex::RegexMatcher::new("lorem").unwrap();
+        grep::searcher::SearcherBuilder::new()
+            .line_number(true)
+            .build()
+            .search_slice(
+                &matcher,
+                b"lorem\nlorem\nlorem\n",
+                MatchesSink::new(&matcher, &mut matches, Some(2), &SearchControl::default()),
+            )
+            .unwrap();
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1].line_number, 2);
+    }
+
+    #[test]
+    fn pauses_at_results_limit_until_continued() {
+        let control = SearchControl::new(Some(3), None);
+        let matcher = grep::regex::RegexMatcher::new("lorem").unwrap();
+        let (paused_tx, paused_rx) = std::sync::mpsc::channel();
+        let mut matches = Vec::new();
+
+        std::thread::scope(|scope| {
+            let searcher = scope.spawn(|| {
+                let sink = MatchesSink::new(&matcher, &mut matches, None, &control)
+                    .on_pause(|matches| paused_tx.send(matches.len()).unwrap());
+                grep::searcher::SearcherBuilder::new()
+                    .line_number(true)
+                    .build()
+                    .search_slice(&matcher, "lorem\n".repeat(10).as_bytes(), sink)
+                    .unwrap();
+            });
+
+            for _ in 0..3 {
+                assert_eq!(paused_rx.recv().unwrap(), 3);
+                while !control.is_paused() {
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+                control.continue_search();
+            }
+            searcher.join().unwrap();
+        });
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line_number, 10);
+    }
+
+    #[test]
+    fn inverted_matches() {
+        let matches = search_inverted("lorem\nipsum\nlorem\n", "lorem");
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line_number, 2);
+        assert_eq!(matches[0].text, "ipsum\n");
+        assert!(matches[0].match_offsets.is_empty());
+    }
+
+    #[test]
+    fn multi_line_without_offsets() {
+        let matches = split_by_lines(0, "lorem\nipsum\n", vec![]);
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1].line_number, 1);
+        assert_eq!(matches[1].text, "ipsum");
+        assert!(matches[1].match_offsets.is_empty());
+    }
+
+    #[test]
+    fn context_lines() {
+        let matches = search("a\nb\nc\nd\ne\n", "c", 1, 2);
+
+        let lines = matches
+            .iter()
+            .map(|m| (m.line_number, m.is_context))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(2, true), (3, false), (4, true), (5, true)]);
+        assert_eq!(matches[0].text, "b\n");
     }
 
     #[test]
     fn multi_line() {
         let text = "lorem\nipsum dolor\nsit amet\n";
-        let matches = split_by_lines(0, text, vec![(0, 21)]);
+        let matches = split_by_lines(0, text, vec![(0, 21, 0)]);
 
         assert_eq!(matches.len(), 3);
         let (m1, m2, m3) = (&matches[0], &matches[1], &matches[2]);
         assert_eq!(m1.line_number, 0);
-        assert_eq!(m1.match_offsets, &[(0, 5)]);
+        assert_eq!(m1.match_offsets, &[(0, 5, 0)]);
         assert_eq!(m1.text, "lorem");
 
         assert_eq!(m2.line_number, 1);
-        assert_eq!(m2.match_offsets, &[(0, 11)]);
+        assert_eq!(m2.match_offsets, &[(0, 11, 0)]);
         assert_eq!(m2.text, "ipsum dolor");
 
         assert_eq!(m3.line_number, 2);
-        assert_eq!(m3.match_offsets, &[(0, 3)]);
+        assert_eq!(m3.match_offsets, &[(0, 3, 0)]);
         assert_eq!(m3.text, "sit amet");
     }
 }
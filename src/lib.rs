@@ -1,5 +1,6 @@
 pub mod app;
 pub mod args;
 pub mod editor;
+pub mod history;
 pub mod ig;
 pub mod ui;
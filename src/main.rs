@@ -1,23 +1,24 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use igrep::{
     app::App,
     args::Args,
     editor::EditorCommand,
-    ig,
+    history::History,
+    ig::{self, file_reader, Engine},
     ui::{
         context_viewer::ContextViewer,
+        search_popup::SearchPopup,
         theme::{dark::Dark, light::Light, Theme, ThemeVariant},
     },
 };
-use std::io::Write;
+use std::{fs::File, io::Write, path::Path};
 
 fn main() -> Result<()> {
-    let args = Args::parse_cli_and_config_file();
+    let mut args = Args::parse_cli_and_config_file();
 
     if args.type_list {
         use itertools::Itertools;
-        let mut builder = ignore::types::TypesBuilder::new();
-        builder.add_defaults();
+        let builder = ig::search_config::types_builder(&args.type_add, &args.type_clear)?;
         for definition in builder.definitions() {
             writeln!(
                 std::io::stdout(),
@@ -29,13 +30,57 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let paths = if args.paths.is_empty() {
-        vec!["./".into()]
+    let patterns = args.take_patterns()?;
+
+    // piped input is searched when no paths are given, or explicitly with `-` path
+    let stdin_path = Path::new("-");
+    let file_list = args
+        .files_from
+        .as_deref()
+        .map(file_reader::read_file_list)
+        .transpose()
+        .context("Failed to read the list of files")?;
+    // errors of all searches are appended to the log, which is created once at startup
+    let error_log = args
+        .error_log
+        .as_deref()
+        .map(|path| {
+            File::create(path)
+                .with_context(|| format!("Cannot create error log '{}'", path.display()))
+        })
+        .transpose()?;
+    let stdin_file = if file_list.is_none()
+        && (args.paths.iter().any(|path| path == stdin_path)
+            || (args.paths.is_empty() && grep::cli::is_readable_stdin()))
+    {
+        Some(file_reader::capture_stdin().context("Failed to read standard input")?)
     } else {
-        args.paths
+        None
+    };
+
+    // removed when `stdin_file` is dropped, also if any of the following steps fails
+    let captured_path = stdin_file
+        .as_ref()
+        .map(|stdin_file| stdin_file.path().to_path_buf());
+
+    let paths = match &captured_path {
+        Some(stdin_file) if args.paths.is_empty() => vec![stdin_file.clone()],
+        Some(stdin_file) => args
+            .paths
+            .into_iter()
+            .map(|path| {
+                if path == stdin_path {
+                    stdin_file.clone()
+                } else {
+                    path
+                }
+            })
+            .collect(),
+        None if args.paths.is_empty() => vec!["./".into()],
+        None => args.paths,
     };
 
-    let search_config = ig::SearchConfig::from(args.pattern.unwrap(), paths)?
+    let search_config = ig::SearchConfig::from(patterns, paths)?
         .case_insensitive(args.ignore_case)
         .case_smart(args.smart_case)
         .search_hidden(args.search_hidden)
@@ -43,21 +88,56 @@ fn main() -> Result<()> {
         .word_regexp(args.word_regexp)
         .fixed_strings(args.fixed_strings)
         .multi_line(args.multi_line)
+        .invert_match(args.invert_match)
+        .encoding(args.encoding)?
+        .search_zip(args.search_zip)
+        .pre(args.pre, args.pre_glob)?
+        .max_depth(args.max_depth)
+        .max_filesize(args.max_filesize)
+        .max_count(args.max_count)
+        .max_results(args.max_results)
+        .max_memory(args.max_memory)
+        .one_file_system(args.one_file_system)
+        .no_ignore(args.no_ignore)
+        .no_ignore_vcs(args.no_ignore_vcs)
+        .no_ignore_parent(args.no_ignore_parent)
+        .no_ignore_files(args.no_ignore_files)
+        .ignore_files(args.ignore_file)?
+        .unrestricted(args.unrestricted)
+        .stdin_file(captured_path)
+        .file_list(file_list)
+        .after_context(args.after_context.or(args.context).unwrap_or_default())
+        .before_context(args.before_context.or(args.context).unwrap_or_default())
         .globs(args.glob)?
-        .file_types(args.type_matching, args.type_not)?
-        .sort_by(args.sort_by, args.sort_by_reverse)?;
+        .file_types(
+            args.type_matching,
+            args.type_not,
+            &args.type_add,
+            &args.type_clear,
+        )?
+        .sort_by(args.sort_by, args.sort_by_reverse)?
+        .engine(if args.pcre2 {
+            Engine::Pcre2
+        } else {
+            args.engine
+        })?;
 
     let theme: Box<dyn Theme> = match args.theme {
         ThemeVariant::Light => Box::new(Light),
         ThemeVariant::Dark => Box::new(Dark),
     };
+    let history = History::load(&search_config);
     let mut app = App::new(
         search_config,
         EditorCommand::new(args.editor.custom_command, args.editor.editor)?,
         ContextViewer::new(args.context_viewer),
+        SearchPopup::new(!args.no_search_as_you_type),
+        history,
         theme,
+        error_log,
     );
-    app.run()?;
+    let result = app.run();
+    drop(stdin_file);
 
-    Ok(())
+    result
 }
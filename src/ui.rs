@@ -1,9 +1,12 @@
 pub mod bottom_bar;
 pub mod context_viewer;
+pub mod errors_popup;
 pub mod input_handler;
 pub mod keymap_popup;
 pub mod result_list;
 pub mod search_popup;
+pub mod stats_popup;
 pub mod theme;
 
+mod fuzzy;
 mod scroll_offset_list;
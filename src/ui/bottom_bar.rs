@@ -5,8 +5,9 @@ use ratatui::{
     widgets::Paragraph,
     Frame,
 };
+use unicode_width::UnicodeWidthStr;
 
-use crate::ig::Ig;
+use crate::ig::{Ig, SearchConfig};
 
 use super::{
     input_handler::{InputHandler, InputState},
@@ -19,10 +20,12 @@ pub fn draw(
     area: Rect,
     result_list: &ResultList,
     ig: &Ig,
+    search_config: &SearchConfig,
     input_handler: &InputHandler,
     theme: &dyn Theme,
 ) {
     let selected_info_text = render_selected_info_text(result_list);
+    let search_options_text = render_search_options_text(search_config);
 
     let hsplit = Layout::default()
         .direction(Direction::Horizontal)
@@ -30,8 +33,10 @@ pub fn draw(
             [
                 Constraint::Length(12),
                 Constraint::Min(1),
+                // options like `size≤` have multibyte characters
+                Constraint::Length(search_options_text.width() as u16),
                 Constraint::Length(2),
-                Constraint::Length(selected_info_text.len() as u16),
+                Constraint::Length(selected_info_text.width() as u16),
             ]
             .as_ref(),
         )
@@ -39,12 +44,15 @@ pub fn draw(
 
     draw_app_status(frame, hsplit[0], ig, theme);
     draw_search_result_summary(frame, hsplit[1], ig, result_list, theme);
-    draw_current_input(frame, hsplit[2], input_handler, theme);
-    draw_selected_info(frame, hsplit[3], selected_info_text, theme);
+    draw_search_options(frame, hsplit[2], search_options_text, theme);
+    draw_current_input(frame, hsplit[3], input_handler, theme);
+    draw_selected_info(frame, hsplit[4], selected_info_text, theme);
 }
 
 fn draw_app_status(frame: &mut Frame, area: Rect, ig: &Ig, theme: &dyn Theme) {
-    let (app_status_text, app_status_style) = if ig.is_searching() {
+    let (app_status_text, app_status_style) = if ig.is_paused() {
+        ("PAUSED", theme.searching_state_style())
+    } else if ig.is_searching() {
         ("SEARCHING", theme.searching_state_style())
     } else if ig.last_error().is_some() {
         ("ERROR", theme.error_state_style())
@@ -68,14 +76,40 @@ fn draw_search_result_summary(
     result_list: &ResultList,
     theme: &dyn Theme,
 ) {
-    let search_result = Span::raw(if ig.is_searching() {
-        "".into()
+    let search_result = Span::raw(if ig.is_paused() {
+        format!(
+            " Results truncated at {} matches, press Shift-c to continue.",
+            result_list.get_total_number_of_matches()
+        )
+    } else if ig.is_searching() {
+        let stats = ig.stats();
+        let binary_str = match stats.binary_files_skipped {
+            0 => String::default(),
+            skipped => format!(", {skipped} binary skipped"),
+        };
+        let errors_str = match ig.errors().len() {
+            0 => String::default(),
+            1 => ", 1 error".into(),
+            errors_count => format!(", {errors_count} errors"),
+        };
+        format!(
+            " Searched {}/{} files, {}{binary_str}{errors_str} in {:.1}s",
+            stats.files_searched,
+            stats.files_walked,
+            format_bytes(stats.bytes_searched),
+            ig.elapsed().as_secs_f64()
+        )
     } else if let Some(err) = ig.last_error() {
         format!(" {err}")
     } else {
+        let errors_str = match ig.errors().len() {
+            0 => String::default(),
+            1 => " 1 error, press Shift-e to show.".into(),
+            errors_count => format!(" {errors_count} errors, press Shift-e to show."),
+        };
         let total_no_of_matches = result_list.get_total_number_of_matches();
         if total_no_of_matches == 0 {
-            " No matches found.".into()
+            format!(" No matches found.{errors_str}")
         } else {
             let no_of_files = result_list.get_total_number_of_file_entries();
 
@@ -87,13 +121,17 @@ fn draw_search_result_summary(
             let files_str = if no_of_files == 1 { "file" } else { "files" };
 
             let filtered_count = result_list.get_filtered_matches_count();
-            let filtered_str = if filtered_count != 0 {
-                format!(" ({filtered_count} filtered out)")
-            } else {
-                String::default()
+            let refinements_count = result_list.get_refinements_count();
+            let filtered_str = match (filtered_count, refinements_count) {
+                (0, 0) => String::default(),
+                (_, 0) => format!(" ({filtered_count} filtered out)"),
+                (_, 1) => format!(" ({filtered_count} filtered out, 1 refinement)"),
+                (_, _) => {
+                    format!(" ({filtered_count} filtered out, {refinements_count} refinements)")
+                }
             };
 
-            format!(" Found {total_no_of_matches} {matches_str} in {no_of_files} {files_str}{filtered_str}.")
+            format!(" Found {total_no_of_matches} {matches_str} in {no_of_files} {files_str}{filtered_str}.{errors_str}")
         }
     });
 
@@ -105,6 +143,92 @@ fn draw_search_result_summary(
     );
 }
 
+fn render_search_options_text(search_config: &SearchConfig) -> String {
+    let mut options = Vec::new();
+    if let Some(sort_by) = search_config.sort_by {
+        let flag = if sort_by.is_reversed() {
+            "sortr"
+        } else {
+            "sort"
+        };
+        options.push(format!("{flag}:{}", sort_by.name()));
+    }
+    if let Some(max_depth) = search_config.max_depth {
+        options.push(format!("depth≤{max_depth}"));
+    }
+    if let Some(max_filesize) = search_config.max_filesize {
+        options.push(format!("size≤{}", format_filesize(max_filesize)));
+    }
+    if let Some(max_count) = search_config.max_count {
+        options.push(format!("count≤{max_count}"));
+    }
+    if search_config.one_file_system {
+        options.push("one-fs".into());
+    }
+    if search_config.no_ignore {
+        options.push("no-ignore".into());
+    } else {
+        if search_config.no_ignore_vcs {
+            options.push("no-ignore-vcs".into());
+        }
+        if search_config.no_ignore_parent {
+            options.push("no-ignore-parent".into());
+        }
+    }
+    if search_config.no_ignore_files && !search_config.ignore_files.is_empty() {
+        options.push("no-ignore-files".into());
+    }
+    if search_config.search_hidden {
+        options.push("hidden".into());
+    }
+    if search_config.search_binary {
+        options.push("binary".into());
+    }
+
+    if options.is_empty() {
+        String::default()
+    } else {
+        format!(" {} ", options.join(" "))
+    }
+}
+
+fn format_filesize(size: u64) -> String {
+    [(1 << 30, "G"), (1 << 20, "M"), (1 << 10, "K")]
+        .into_iter()
+        .find(|(multiplier, _)| size >= *multiplier && size.is_multiple_of(*multiplier))
+        .map_or_else(
+            || size.to_string(),
+            |(multiplier, suffix)| format!("{}{suffix}", size / multiplier),
+        )
+}
+
+/// Formats the size in the largest fitting unit with one decimal place, e.g. 1.5M.
+pub(super) fn format_bytes(bytes: u64) -> String {
+    [(1 << 30, "G"), (1 << 20, "M"), (1 << 10, "K")]
+        .into_iter()
+        .find(|(multiplier, _)| bytes >= *multiplier)
+        .map_or_else(
+            || format!("{bytes}B"),
+            |(multiplier, suffix)| format!("{:.1}{suffix}", bytes as f64 / multiplier as f64),
+        )
+}
+
+fn draw_search_options(
+    frame: &mut Frame,
+    area: Rect,
+    search_options_text: String,
+    theme: &dyn Theme,
+) {
+    let search_options = Span::styled(search_options_text, theme.bottom_bar_style());
+
+    frame.render_widget(
+        Paragraph::new(search_options)
+            .style(theme.bottom_bar_style())
+            .alignment(Alignment::Right),
+        area,
+    );
+}
+
 fn draw_current_input(
     frame: &mut Frame,
     area: Rect,
@@ -1,7 +1,6 @@
 use std::{
     borrow::BorrowMut,
     cmp::max,
-    io::BufRead,
     path::{Path, PathBuf},
 };
 
@@ -15,12 +14,14 @@ use ratatui::{
     Frame,
 };
 use syntect::{
-    easy::HighlightFile,
+    easy::HighlightLines,
     highlighting::{self, ThemeSet},
     parsing::SyntaxSet,
+    util::LinesWithEndings,
 };
 
 use super::{result_list::ResultList, theme::Theme};
+use crate::ig::{file_reader, SearchConfig};
 
 #[derive(Default, Clone, Debug, PartialEq, Eq, ValueEnum)]
 pub enum ContextViewerPosition {
@@ -80,7 +81,12 @@ impl ContextViewer {
         self.size = (self.size - Self::SIZE_CHANGE_DELTA).max(Self::MIN_SIZE);
     }
 
-    pub fn update_if_needed(&mut self, file_path: impl AsRef<Path>, theme: &dyn Theme) {
+    pub fn update_if_needed(
+        &mut self,
+        file_path: impl AsRef<Path>,
+        search_config: &SearchConfig,
+        theme: &dyn Theme,
+    ) {
         if self.position == ContextViewerPosition::None
             || self.highlighted_file_path == file_path.as_ref()
         {
@@ -90,23 +96,22 @@ impl ContextViewer {
         self.highlighted_file_path = file_path.as_ref().into();
         self.file_highlighted.clear();
 
-        let mut highlighter = HighlightFile::new(
-            file_path,
-            &self.syntax_set,
-            &self.theme_set.themes[theme.context_viewer_theme()],
-        )
-        .expect("Failed to create line highlighter");
-        let mut line = String::new();
-
-        while highlighter
-            .reader
-            .read_line(&mut line)
-            .expect("Not valid UTF-8")
-            > 0
-        {
+        let Ok(content) = file_reader::read_to_string(&file_path, search_config) else {
+            return;
+        };
+
+        let syntax = self
+            .syntax_set
+            .find_syntax_for_file(file_path)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let mut highlighter =
+            HighlightLines::new(syntax, &self.theme_set.themes[theme.context_viewer_theme()]);
+
+        for line in LinesWithEndings::from(&content) {
             let regions: Vec<(highlighting::Style, &str)> = highlighter
-                .highlight_lines
-                .highlight_line(&line, &self.syntax_set)
+                .highlight_line(line, &self.syntax_set)
                 .expect("Failed to highlight line");
 
             let span_vec = regions
@@ -115,7 +120,6 @@ impl ContextViewer {
                 .collect();
 
             self.file_highlighted.push(span_vec);
-            line.clear(); // read_line appends so we need to clear between lines
         }
     }
 
@@ -202,7 +206,10 @@ impl ContextViewer {
             .collect_vec();
 
         let match_offset = match_index - max(first_line_index, 1);
-        let styled_line = &mut styled_spans[match_offset];
+        // file might have been changed or removed since it was searched
+        let Some(styled_line) = styled_spans.get_mut(match_offset) else {
+            return styled_spans;
+        };
         let line_width = styled_line.width();
         let span_vec = &mut styled_line.spans;
 
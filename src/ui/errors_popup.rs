@@ -0,0 +1,80 @@
+use ratatui::{
+    layout::{Alignment, Rect},
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Padding, Paragraph},
+    Frame,
+};
+
+use super::theme::Theme;
+
+/// Lists files and directories which could not be walked or searched.
+#[derive(Default)]
+pub struct ErrorsPopup {
+    visible: bool,
+    scroll_y: u16,
+}
+
+impl ErrorsPopup {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        if self.visible {
+            self.scroll_y = 0;
+        }
+    }
+
+    pub fn go_down(&mut self) {
+        self.scroll_y = self.scroll_y.saturating_add(1);
+    }
+
+    pub fn go_up(&mut self) {
+        self.scroll_y = self.scroll_y.saturating_sub(1);
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, errors: &[String], theme: &dyn Theme) {
+        if !self.visible {
+            return;
+        }
+
+        let lines = if errors.is_empty() {
+            vec![Line::from("No errors.")]
+        } else {
+            errors
+                .iter()
+                .map(|error| Line::from(error.as_str()))
+                .collect()
+        };
+
+        let popup_area = Self::get_popup_area(frame.size(), lines.len() as u16);
+        let max_y = (lines.len() as u16).saturating_sub(popup_area.height.saturating_sub(4));
+        self.scroll_y = self.scroll_y.min(max_y);
+
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(theme.search_popup_border())
+                    .title(format!(" Errors ({}) ", errors.len()))
+                    .title_alignment(Alignment::Center)
+                    .padding(Padding::uniform(1)),
+            )
+            .scroll((self.scroll_y, 0));
+
+        frame.render_widget(Clear, popup_area);
+        frame.render_widget(paragraph, popup_area);
+    }
+
+    fn get_popup_area(frame_size: Rect, lines_count: u16) -> Rect {
+        let height = (lines_count + 4).min((frame_size.height as f64 * 0.8) as u16);
+        let y = (frame_size.height - height) / 2;
+
+        let width = (frame_size.width as f64 * 0.8) as u16;
+        let x = (frame_size.width - width) / 2;
+
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
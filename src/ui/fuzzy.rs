@@ -0,0 +1,191 @@
+//! Fuzzy matching in the spirit of fzf: characters of a term have to appear in the text
+//! in the same order, the score prefers consecutive characters and word beginnings.
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 8;
+const BONUS_BOUNDARY: i64 = 8;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+/// Matches every whitespace separated term of the query against the text made of `parts`,
+/// which are matched as if they were concatenated, without allocating it.
+/// Returns the total score and sorted byte offsets of matched characters in the concatenation.
+pub fn fuzzy_match(query: &str, parts: &[&str]) -> Option<(i64, Vec<usize>)> {
+    let mut score = 0;
+    let mut positions = Vec::new();
+    for term in query.split_whitespace() {
+        score += fuzzy_match_term(term, parts, Some(&mut positions))?;
+    }
+    positions.sort_unstable();
+    positions.dedup();
+
+    Some((score, positions))
+}
+
+/// Same as [`fuzzy_match`], but only scores the text, it does not allocate.
+pub fn fuzzy_score(query: &str, parts: &[&str]) -> Option<i64> {
+    query
+        .split_whitespace()
+        .map(|term| fuzzy_match_term(term, parts, None))
+        .sum()
+}
+
+/// Characters of the concatenated parts with their byte offsets.
+fn char_indices<'a>(parts: &'a [&'a str]) -> impl DoubleEndedIterator<Item = (usize, char)> + 'a {
+    parts
+        .iter()
+        .enumerate()
+        .flat_map(move |(part_index, part)| {
+            let base = parts[..part_index]
+                .iter()
+                .map(|part| part.len())
+                .sum::<usize>();
+            part.char_indices()
+                .map(move |(offset, c)| (base + offset, c))
+        })
+}
+
+/// Matching is case insensitive, unless the term contains an uppercase character.
+fn fuzzy_match_term(
+    term: &str,
+    parts: &[&str],
+    mut positions: Option<&mut Vec<usize>>,
+) -> Option<i64> {
+    let ignore_case = !term.chars().any(char::is_uppercase);
+    let eq = |a: char, b: char| {
+        if ignore_case {
+            a == b || a.to_lowercase().eq(b.to_lowercase())
+        } else {
+            a == b
+        }
+    };
+
+    // find where the first occurrence of the whole term ends
+    let mut term_chars = term.chars();
+    let mut wanted = term_chars.next()?;
+    let mut end = None;
+    for (offset, c) in char_indices(parts) {
+        if eq(c, wanted) {
+            match term_chars.next() {
+                Some(next) => wanted = next,
+                None => {
+                    end = Some(offset);
+                    break;
+                }
+            }
+        }
+    }
+    let end = end?;
+
+    // then go back to find the shortest window ending there
+    let mut term_chars = term.chars().rev();
+    let mut wanted = term_chars.next()?;
+    let mut start = end;
+    for (offset, c) in char_indices(parts)
+        .rev()
+        .skip_while(|&(offset, _)| offset > end)
+    {
+        if eq(c, wanted) {
+            match term_chars.next() {
+                Some(next) => wanted = next,
+                None => {
+                    start = offset;
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut score = 0;
+    let mut term_chars = term.chars().peekable();
+    let mut previous_char = None;
+    let mut previous_match: Option<usize> = None;
+    for (index, (offset, c)) in char_indices(parts).enumerate() {
+        if offset > end {
+            break;
+        }
+        let is_match = offset >= start && term_chars.peek().is_some_and(|&wanted| eq(c, wanted));
+        if is_match {
+            term_chars.next();
+            score += SCORE_MATCH;
+            match previous_match {
+                Some(previous) if previous + 1 == index => score += BONUS_CONSECUTIVE,
+                Some(previous) => {
+                    score -=
+                        PENALTY_GAP_START + PENALTY_GAP_EXTENSION * (index - previous - 1) as i64
+                }
+                None => (),
+            }
+            if is_boundary(previous_char, c) {
+                score += BONUS_BOUNDARY;
+            }
+            if let Some(positions) = positions.as_deref_mut() {
+                positions.push(offset);
+            }
+            previous_match = Some(index);
+        }
+        previous_char = Some(c);
+    }
+
+    Some(score)
+}
+
+fn is_boundary(previous: Option<char>, current: char) -> bool {
+    match previous {
+        None => true,
+        Some(previous) => {
+            !previous.is_alphanumeric() || (previous.is_lowercase() && current.is_uppercase())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_in_order() {
+        assert_eq!(fuzzy_match("fbr", &["foo bar"]).unwrap().1, [0, 4, 6]);
+        assert_eq!(fuzzy_match("rbf", &["foo bar"]), None);
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(fuzzy_match("foo", &["FOO"]).is_some());
+        assert!(fuzzy_match("Foo", &["foo"]).is_none());
+    }
+
+    #[test]
+    fn all_terms_have_to_match() {
+        assert_eq!(
+            fuzzy_match("bar foo", &["foo bar"]).unwrap().1,
+            [0, 1, 2, 4, 5, 6]
+        );
+        assert_eq!(fuzzy_match("bar baz", &["foo bar"]), None);
+    }
+
+    #[test]
+    fn prefers_consecutive_characters() {
+        let (consecutive, _) = fuzzy_match("main", &["src/main.rs"]).unwrap();
+        let (scattered, _) = fuzzy_match("main", &["my_app/init.rs"]).unwrap();
+        assert!(consecutive > scattered);
+    }
+
+    #[test]
+    fn parts_are_matched_as_one_text() {
+        assert_eq!(
+            fuzzy_match("ab", &["xa", " ", "b"]),
+            fuzzy_match("ab", &["xa b"])
+        );
+        assert_eq!(fuzzy_match("ab", &["xa", " ", "b"]).unwrap().1, [1, 3]);
+        assert_eq!(
+            fuzzy_score("ab", &["xa", " ", "b"]),
+            fuzzy_match("ab", &["xa b"]).map(|(score, _)| score)
+        );
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        assert_eq!(fuzzy_match("żb", &["aż b"]).unwrap().1, [1, 4]);
+    }
+}
@@ -25,9 +25,19 @@ pub enum InputMode {
     Normal,
     TextInsertion,
     Keymap,
+    Stats,
+    Errors,
 }
 
 impl InputHandler {
+    /// Handler for text typed into a popup open at startup.
+    pub fn text_insertion() -> Self {
+        Self {
+            input_mode: InputMode::TextInsertion,
+            ..Default::default()
+        }
+    }
+
     pub fn handle_input<A: Application>(&mut self, app: &mut A) -> Result<()> {
         let poll_timeout = if app.is_searching() {
             Duration::from_millis(1)
@@ -50,6 +60,8 @@ impl InputHandler {
                             self.handle_key_in_text_insertion_mode(key_event, app)
                         }
                         InputMode::Keymap => self.handle_key_in_keymap_mode(key_event, app),
+                        InputMode::Stats => self.handle_key_in_stats_mode(app),
+                        InputMode::Errors => self.handle_key_in_errors_mode(key_event, app),
                     }
                 }
             }
@@ -94,6 +106,11 @@ impl InputHandler {
                 self.input_mode = InputMode::Normal;
                 app.on_toggle_popup();
             }
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => app.on_history_search(),
             KeyEvent {
                 code: KeyCode::Char(c),
                 modifiers: modifier,
@@ -121,12 +138,26 @@ impl InputHandler {
                 code: KeyCode::Right,
                 ..
             } => app.on_char_right(),
+            KeyEvent {
+                code: KeyCode::Up, ..
+            } => app.on_history_previous(),
+            KeyEvent {
+                code: KeyCode::Down,
+                ..
+            } => app.on_history_next(),
+            KeyEvent {
+                code: KeyCode::Tab, ..
+            } => app.on_next_pattern(),
+            KeyEvent {
+                code: KeyCode::BackTab,
+                ..
+            } => app.on_previous_pattern(),
+            // the popup stays open if the pattern does not compile
             KeyEvent {
                 code: KeyCode::Enter,
                 ..
-            } => {
+            } if app.on_search() => {
                 self.input_mode = InputMode::Normal;
-                app.on_search();
                 app.on_toggle_popup();
             }
             _ => (),
@@ -173,6 +204,23 @@ impl InputHandler {
         }
     }
 
+    /// Any key closes the statistics popup.
+    fn handle_key_in_stats_mode<A: Application>(&mut self, app: &mut A) {
+        self.input_mode = InputMode::Normal;
+        app.on_toggle_stats();
+    }
+
+    fn handle_key_in_errors_mode<A: Application>(&mut self, key_event: KeyEvent, app: &mut A) {
+        match key_event.code {
+            KeyCode::Up | KeyCode::Char('k') => app.on_errors_up(),
+            KeyCode::Down | KeyCode::Char('j') => app.on_errors_down(),
+            _ => {
+                self.input_mode = InputMode::Normal;
+                app.on_toggle_errors();
+            }
+        }
+    }
+
     fn handle_char_input<A: Application>(&mut self, character: char, app: &mut A) {
         self.input_buffer.push(character);
         self.input_state = InputState::Valid;
@@ -227,6 +275,61 @@ impl InputHandler {
             "c" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
                 app.on_toggle_sort_ctime()
             }),
+            "#" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_toggle_sort_matches()
+            }),
+            "z" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_toggle_sort_size()
+            }),
+            "p" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_toggle_sort_depth()
+            }),
+            "N" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_toggle_sort_natural()
+            }),
+            // refine
+            "r" => {
+                self.input_mode = InputMode::TextInsertion;
+                consume_buffer_and_execute(&mut self.input_buffer, &mut || app.on_refine_keep())
+            }
+            "R" => {
+                self.input_mode = InputMode::TextInsertion;
+                consume_buffer_and_execute(&mut self.input_buffer, &mut || app.on_refine_discard())
+            }
+            "u" => {
+                consume_buffer_and_execute(&mut self.input_buffer, &mut || app.on_undo_refinement())
+            }
+            "f" => {
+                self.input_mode = InputMode::TextInsertion;
+                consume_buffer_and_execute(&mut self.input_buffer, &mut || app.on_fuzzy_filter())
+            }
+            "C" => {
+                consume_buffer_and_execute(&mut self.input_buffer, &mut || app.on_continue_search())
+            }
+            "S" => {
+                self.input_mode = InputMode::Stats;
+                consume_buffer_and_execute(&mut self.input_buffer, &mut || app.on_toggle_stats())
+            }
+            "E" => {
+                self.input_mode = InputMode::Errors;
+                consume_buffer_and_execute(&mut self.input_buffer, &mut || app.on_toggle_errors())
+            }
+            // ignore rules
+            "ii" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_toggle_no_ignore()
+            }),
+            "iv" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_toggle_no_ignore_vcs()
+            }),
+            "ip" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_toggle_no_ignore_parent()
+            }),
+            "if" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_toggle_no_ignore_files()
+            }),
+            "iu" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
+                app.on_cycle_unrestricted()
+            }),
             // misc
             "q" => consume_buffer_and_execute(&mut self.input_buffer, &mut || app.on_exit()),
             "?" => consume_buffer_and_execute(&mut self.input_buffer, &mut || {
@@ -240,6 +343,7 @@ impl InputHandler {
             // buffer for multikey inputs
             "g" => self.input_state = InputState::Incomplete("g…".into()),
             "d" => self.input_state = InputState::Incomplete("d…".into()),
+            "i" => self.input_state = InputState::Incomplete("i…".into()),
             buf => {
                 self.input_state = InputState::Invalid(buf.into());
                 self.input_buffer.clear();
@@ -267,6 +371,8 @@ impl InputHandler {
                 self.input_mode = InputMode::TextInsertion;
                 app.on_toggle_popup();
             }
+            // Esc quits unless a key series is in progress
+            #[allow(clippy::collapsible_match)]
             KeyCode::Esc => {
                 if matches!(self.input_state, InputState::Valid)
                     || matches!(self.input_state, InputState::Invalid(_))
@@ -320,6 +426,14 @@ mod tests {
         input_handler.handle_key_in_keymap_mode(key_event, app);
     }
 
+    fn handle_key_text_insertion_mode<A: Application>(key_event: KeyEvent, app: &mut A) {
+        let mut input_handler = InputHandler {
+            input_mode: InputMode::TextInsertion,
+            ..Default::default()
+        };
+        input_handler.handle_key_in_text_insertion_mode(key_event, app);
+    }
+
     #[test_case(KeyCode::Down; "down")]
     #[test_case(Char('j'); "j")]
     fn next_match(key_code: KeyCode) {
@@ -413,6 +527,175 @@ mod tests {
         handle_key(KeyCode::Char('s'), &mut app_mock);
     }
 
+    #[test]
+    fn refine_keep() {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_refine_keep().once().return_const(());
+        handle_key(Char('r'), &mut app_mock);
+    }
+
+    #[test]
+    fn refine_discard() {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_refine_discard().once().return_const(());
+        handle_key(Char('R'), &mut app_mock);
+    }
+
+    #[test]
+    fn undo_refinement() {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_undo_refinement().once().return_const(());
+        handle_key(Char('u'), &mut app_mock);
+    }
+
+    #[test_case(true, 1, InputMode::Normal; "valid pattern")]
+    #[test_case(false, 0, InputMode::TextInsertion; "invalid pattern")]
+    fn confirm_search(valid: bool, toggles: usize, input_mode: InputMode) {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_search().once().return_const(valid);
+        app_mock
+            .expect_on_toggle_popup()
+            .times(toggles)
+            .return_const(());
+        let mut input_handler = InputHandler {
+            input_mode: InputMode::TextInsertion,
+            ..Default::default()
+        };
+        input_handler
+            .handle_key_in_text_insertion_mode(KeyEvent::from(KeyCode::Enter), &mut app_mock);
+        assert_eq!(input_handler.input_mode, input_mode);
+    }
+
+    #[test]
+    fn recall_history() {
+        let mut app_mock = MockApplication::default();
+        app_mock
+            .expect_on_history_previous()
+            .once()
+            .return_const(());
+        app_mock.expect_on_history_next().once().return_const(());
+        app_mock.expect_on_history_search().once().return_const(());
+        handle_key_text_insertion_mode(KeyEvent::from(KeyCode::Up), &mut app_mock);
+        handle_key_text_insertion_mode(KeyEvent::from(KeyCode::Down), &mut app_mock);
+        handle_key_text_insertion_mode(
+            KeyEvent::new(Char('r'), KeyModifiers::CONTROL),
+            &mut app_mock,
+        );
+    }
+
+    #[test]
+    fn fuzzy_filter() {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_fuzzy_filter().once().return_const(());
+        handle_key(Char('f'), &mut app_mock);
+    }
+
+    #[test]
+    fn toggle_sort_by_results() {
+        let mut app_mock = MockApplication::default();
+        app_mock
+            .expect_on_toggle_sort_matches()
+            .once()
+            .return_const(());
+        app_mock
+            .expect_on_toggle_sort_size()
+            .once()
+            .return_const(());
+        app_mock
+            .expect_on_toggle_sort_depth()
+            .once()
+            .return_const(());
+        app_mock
+            .expect_on_toggle_sort_natural()
+            .once()
+            .return_const(());
+        handle_key_series(&[Char('#'), Char('z'), Char('p'), Char('N')], &mut app_mock);
+    }
+
+    #[test]
+    fn stats_open_and_close() {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_toggle_stats().times(2).return_const(());
+        let mut input_handler = InputHandler::default();
+        handle(&mut input_handler, Char('S'), &mut app_mock);
+        assert_eq!(input_handler.input_mode, InputMode::Stats);
+        input_handler.handle_key_in_stats_mode(&mut app_mock);
+        assert_eq!(input_handler.input_mode, InputMode::Normal);
+    }
+
+    #[test]
+    fn errors_open_scroll_and_close() {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_toggle_errors().times(2).return_const(());
+        app_mock.expect_on_errors_down().once().return_const(());
+        app_mock.expect_on_errors_up().once().return_const(());
+        let mut input_handler = InputHandler::default();
+        handle(&mut input_handler, Char('E'), &mut app_mock);
+        assert_eq!(input_handler.input_mode, InputMode::Errors);
+
+        for code in [Char('j'), KeyCode::Up, KeyCode::Esc] {
+            input_handler.handle_key_in_errors_mode(KeyEvent::from(code), &mut app_mock);
+        }
+        assert_eq!(input_handler.input_mode, InputMode::Normal);
+    }
+
+    #[test]
+    fn continue_search() {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_continue_search().once().return_const(());
+        handle_key(Char('C'), &mut app_mock);
+    }
+
+    #[test]
+    fn toggle_no_ignore() {
+        let mut app_mock = MockApplication::default();
+        app_mock
+            .expect_on_toggle_no_ignore()
+            .once()
+            .return_const(());
+        handle_key_series(&[Char('i'), Char('i')], &mut app_mock);
+    }
+
+    #[test]
+    fn toggle_no_ignore_vcs() {
+        let mut app_mock = MockApplication::default();
+        app_mock
+            .expect_on_toggle_no_ignore_vcs()
+            .once()
+            .return_const(());
+        handle_key_series(&[Char('i'), Char('v')], &mut app_mock);
+    }
+
+    #[test]
+    fn toggle_no_ignore_parent() {
+        let mut app_mock = MockApplication::default();
+        app_mock
+            .expect_on_toggle_no_ignore_parent()
+            .once()
+            .return_const(());
+        handle_key_series(&[Char('i'), Char('p')], &mut app_mock);
+    }
+
+    #[test]
+    fn toggle_no_ignore_files() {
+        let mut app_mock = MockApplication::default();
+        app_mock
+            .expect_on_toggle_no_ignore_files()
+            .once()
+            .return_const(());
+        handle_key_series(&[Char('i'), Char('f')], &mut app_mock);
+    }
+
+    #[test]
+    fn cycle_unrestricted() {
+        let mut app_mock = MockApplication::default();
+        app_mock
+            .expect_on_cycle_unrestricted()
+            .once()
+            .return_const(());
+        handle_key_series(&[Char('i'), Char('u')], &mut app_mock);
+    }
+
     #[test]
     fn open_file() {
         let mut app_mock = MockApplication::default();
@@ -428,6 +711,29 @@ mod tests {
         handle_key(key_code, &mut app_mock);
     }
 
+    #[test]
+    fn next_pattern() {
+        let mut app_mock = MockApplication::default();
+        app_mock.expect_on_next_pattern().once().return_const(());
+        handle_key_text_insertion_mode(
+            KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE),
+            &mut app_mock,
+        );
+    }
+
+    #[test]
+    fn previous_pattern() {
+        let mut app_mock = MockApplication::default();
+        app_mock
+            .expect_on_previous_pattern()
+            .once()
+            .return_const(());
+        handle_key_text_insertion_mode(
+            KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT),
+            &mut app_mock,
+        );
+    }
+
     #[test_case(KeyCode::F(1))]
     #[test_case(KeyCode::Char('?'))]
     fn keymap_open(key_code: KeyCode) {
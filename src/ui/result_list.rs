@@ -1,5 +1,3 @@
-use std::cmp;
-
 use ratatui::{
     layout::Rect,
     style::Style,
@@ -8,40 +6,231 @@ use ratatui::{
     Frame,
 };
 
-use crate::ig::file_entry::{EntryType, FileEntry};
+use grep::{matcher::Matcher, regex::RegexMatcher};
+
+use std::{cmp::Ordering, path::Path};
+
+use crate::ig::{
+    file_entry::{EntryType, FileEntry},
+    file_reader, SearchConfig, SortKey,
+};
 
 use super::{
+    fuzzy,
     scroll_offset_list::{List, ListItem, ListState, ScrollOffset},
     theme::Theme,
 };
 
 #[derive(Default)]
 pub struct ResultList {
-    entries: Vec<EntryType>,
+    files: Vec<FileGroup>,
+    /// Index of the first entry of each file among all entries.
+    entry_offsets: Vec<usize>,
+    /// Number of matches in files preceding each file.
+    match_offsets: Vec<usize>,
+    entries_count: usize,
+    current_matches_count: usize,
     state: ListState,
     file_entries_count: usize,
     matches_count: usize,
     filtered_matches_count: usize,
+    refinements: Vec<Refinement>,
+    fuzzy_filter: Option<FuzzyFilter>,
+    sort_key: Option<SortKey>,
+}
+
+/// Entries of a single file, starting with its header.
+#[derive(Clone)]
+struct FileGroup {
+    entries: Vec<EntryType>,
+    /// Indices of matches within `entries`, in ascending order.
+    match_indices: Vec<usize>,
+}
+
+impl FileGroup {
+    fn new(entries: Vec<EntryType>) -> Self {
+        let match_indices = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.is_match())
+            .map(|(index, _)| index)
+            .collect();
+
+        Self {
+            entries,
+            match_indices,
+        }
+    }
+
+    fn header(&self) -> &str {
+        match &self.entries[0] {
+            EntryType::Header(name) => name,
+            _ => panic!("Header not found"),
+        }
+    }
+
+    /// Orders files by a sort key applied to search results, files are ordered by path otherwise.
+    fn compare(&self, other: &Self, sort_key: SortKey) -> Ordering {
+        let ordering = match sort_key {
+            SortKey::Matches | SortKey::MatchesReversed => {
+                self.match_indices.len().cmp(&other.match_indices.len())
+            }
+            SortKey::Depth | SortKey::DepthReversed => {
+                let depth = |file: &Self| Path::new(file.header()).components().count();
+                depth(self).cmp(&depth(other))
+            }
+            _ => Ordering::Equal,
+        };
+        let ordering = if sort_key.is_reversed() {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+
+        ordering.then_with(|| self.header().cmp(other.header()))
+    }
+}
+
+/// Inserts the file in the order of the sort key, or appends it if there is none.
+/// Returns index of the inserted file.
+fn insert_file(files: &mut Vec<FileGroup>, file: FileGroup, sort_key: Option<SortKey>) -> usize {
+    let index = match sort_key {
+        Some(sort_key) => files.partition_point(|other| other.compare(&file, sort_key).is_le()),
+        None => files.len(),
+    };
+    files.insert(index, file);
+    index
+}
+
+/// Fuzzy filter over results, keeps them in the original order to restore it when cleared.
+struct FuzzyFilter {
+    query: String,
+    files: Vec<FileGroup>,
+}
+
+/// Narrows results to matches whose line does (`keep`) or does not match the regex.
+/// Results from before the refinement are kept, so that it can be undone.
+struct Refinement {
+    matcher: RegexMatcher,
+    keep: bool,
+    files: Vec<FileGroup>,
+    filtered_matches_count: usize,
+}
+
+impl Refinement {
+    fn is_kept(&self, text: &str) -> bool {
+        self.matcher.is_match(text.as_bytes()).unwrap_or(false) == self.keep
+    }
+
+    /// Removes matches rejected by the refinement, returns the file, unless no match is left
+    /// in it, and the number of removed matches.
+    fn apply(&self, file: &FileGroup) -> (Option<FileGroup>, usize) {
+        remove_matches(
+            file,
+            |entry| matches!(entry, EntryType::Match(_, text, _) if !self.is_kept(text)),
+        )
+    }
+}
+
+/// Removes matches for which `is_removed` holds, together with context groups left without
+/// any match. Returns the file, unless no match is left in it, and the number of removed matches.
+fn remove_matches(
+    file: &FileGroup,
+    is_removed: impl Fn(&EntryType) -> bool,
+) -> (Option<FileGroup>, usize) {
+    let mut entries = vec![file.entries[0].clone()];
+    let mut removed_matches_count = 0;
+    let mut group = Vec::new();
+
+    let flush_group = |entries: &mut Vec<EntryType>, group: &mut Vec<EntryType>| {
+        if group.iter().any(EntryType::is_match) {
+            if entries.len() > 1 {
+                entries.push(EntryType::Separator);
+            }
+            entries.append(group);
+        }
+        group.clear();
+    };
+
+    for entry in &file.entries[1..] {
+        match entry {
+            EntryType::Separator => flush_group(&mut entries, &mut group),
+            entry if is_removed(entry) => removed_matches_count += 1,
+            _ => group.push(entry.clone()),
+        }
+    }
+    flush_group(&mut entries, &mut group);
+
+    let remaining = (entries.len() > 1).then(|| FileGroup::new(entries));
+    (remaining, removed_matches_count)
 }
 
 impl ResultList {
+    /// Creates a list which keeps files ordered by the sort key, if it cannot be applied
+    /// while walking directories.
+    pub fn new(sort_key: Option<SortKey>) -> Self {
+        Self {
+            sort_key: sort_key.filter(|sort_key| sort_key.is_applied_to_results()),
+            ..Default::default()
+        }
+    }
+
     pub fn add_entry(&mut self, entry: FileEntry) {
         self.file_entries_count += 1;
         self.matches_count += entry.get_matches_count();
 
-        self.entries.append(&mut entry.get_entries());
+        // entries found after refining are refined as well
+        let sort_key = self.sort_key;
+        let mut file = Some(FileGroup::new(entry.get_entries()));
+        let mut removed_matches_count = 0;
+        for refinement in &mut self.refinements {
+            refinement.filtered_matches_count += removed_matches_count;
+
+            if let Some(unrefined) = file.take() {
+                let (refined, removed) = refinement.apply(&unrefined);
+                insert_file(&mut refinement.files, unrefined, sort_key);
+                file = refined;
+                removed_matches_count += removed;
+            }
+        }
+        self.filtered_matches_count += removed_matches_count;
+
+        // fuzzy filtered files are ordered by their score, new ones are appended
+        let file = match (&mut self.fuzzy_filter, file) {
+            (Some(fuzzy_filter), Some(file)) => {
+                let filtered = fuzzy_filtered(&fuzzy_filter.query, std::slice::from_ref(&file));
+                insert_file(&mut fuzzy_filter.files, file, sort_key);
+                filtered.into_iter().next().map(|file| (file, None))
+            }
+            (_, file) => file.map(|file| (file, sort_key)),
+        };
+        if let Some((file, sort_key)) = file {
+            let inserted_entries_count = file.entries.len();
+            let index = insert_file(&mut self.files, file, sort_key);
+            let offset = self
+                .entry_offsets
+                .get(index)
+                .copied()
+                .unwrap_or(self.entries_count);
+            self.update_offsets(index);
+
+            // selected entry stays selected when a file is inserted before it
+            if let Some(selected) = self.state.selected().filter(|&selected| selected >= offset) {
+                self.state.select(Some(selected + inserted_entries_count));
+            }
+        }
 
         if self.state.selected().is_none() {
             self.next_match();
         }
     }
 
-    pub fn iter(&self) -> std::slice::Iter<'_, EntryType> {
-        self.entries.iter()
+    pub fn iter(&self) -> impl Iterator<Item = &EntryType> {
+        self.files.iter().flat_map(|file| file.entries.iter())
     }
 
     pub fn is_empty(&self) -> bool {
-        self.entries.is_empty()
+        self.files.is_empty()
     }
 
     pub fn next_match(&mut self) {
@@ -50,17 +239,8 @@ impl ResultList {
         }
 
         let index = match self.state.selected() {
-            Some(i) => {
-                if i == self.entries.len() - 1 {
-                    i
-                } else {
-                    match self.entries[i + 1] {
-                        EntryType::Header(_) => i + 2,
-                        EntryType::Match(_, _, _) => i + 1,
-                    }
-                }
-            }
-            None => 1,
+            Some(i) => self.nth_match(self.matches_before(i + 1)).unwrap_or(i),
+            None => self.first_match_index(),
         };
 
         self.state.select(Some(index));
@@ -72,17 +252,12 @@ impl ResultList {
         }
 
         let index = match self.state.selected() {
-            Some(i) => {
-                if i == 1 {
-                    1
-                } else {
-                    match self.entries[i - 1] {
-                        EntryType::Header(_) => i - 2,
-                        EntryType::Match(_, _, _) => i - 1,
-                    }
-                }
-            }
-            None => 1,
+            Some(i) => self
+                .matches_before(i)
+                .checked_sub(1)
+                .and_then(|n| self.nth_match(n))
+                .unwrap_or(i),
+            None => self.first_match_index(),
         };
 
         self.state.select(Some(index));
@@ -95,25 +270,12 @@ impl ResultList {
 
         let index = match self.state.selected() {
             Some(i) => {
-                let mut next_index = i;
-                loop {
-                    if next_index == self.entries.len() - 1 {
-                        next_index = i;
-                        break;
-                    }
-
-                    next_index += 1;
-                    match self.entries[next_index] {
-                        EntryType::Header(_) => {
-                            next_index += 1;
-                            break;
-                        }
-                        EntryType::Match(_, _, _) => continue,
-                    }
-                }
-                next_index
+                let (file_index, _) = self.locate(i);
+                let file_end =
+                    self.match_offsets[file_index] + self.files[file_index].match_indices.len();
+                self.nth_match(file_end).unwrap_or(i)
             }
-            None => 1,
+            None => self.first_match_index(),
         };
 
         self.state.select(Some(index));
@@ -126,30 +288,16 @@ impl ResultList {
 
         let index = match self.state.selected() {
             Some(i) => {
-                let mut next_index = i;
-                let mut first_header_visited = false;
-                loop {
-                    if next_index == 1 {
-                        break;
-                    }
-
-                    next_index -= 1;
-                    match self.entries[next_index] {
-                        EntryType::Header(_) => {
-                            if !first_header_visited {
-                                first_header_visited = true;
-                                next_index -= 1;
-                            } else {
-                                next_index += 1;
-                                break;
-                            }
-                        }
-                        EntryType::Match(_, _, _) => continue,
-                    }
-                }
-                next_index
+                let (file_index, _) = self.locate(i);
+                self.match_offsets[file_index]
+                    .checked_sub(1)
+                    .map(|n| self.file_of_match(n))
+                    .and_then(|previous_file_index| {
+                        self.nth_match(self.match_offsets[previous_file_index])
+                    })
+                    .unwrap_or(i)
             }
-            None => 1,
+            None => self.first_match_index(),
         };
 
         self.state.select(Some(index));
@@ -160,7 +308,7 @@ impl ResultList {
             return;
         }
 
-        self.state.select(Some(1));
+        self.state.select(Some(self.first_match_index()));
     }
 
     pub fn bottom(&mut self) {
@@ -168,7 +316,12 @@ impl ResultList {
             return;
         }
 
-        self.state.select(Some(self.entries.len() - 1));
+        let index = self
+            .current_matches_count
+            .checked_sub(1)
+            .and_then(|n| self.nth_match(n))
+            .unwrap_or_else(|| self.first_match_index());
+        self.state.select(Some(index));
     }
 
     pub fn remove_current_entry(&mut self) {
@@ -179,7 +332,7 @@ impl ResultList {
         if self.is_last_match_in_file() {
             self.remove_current_file();
         } else {
-            self.remove_current_entry_and_select_previous();
+            self.remove_current_entry_and_select_next();
         }
     }
 
@@ -189,105 +342,281 @@ impl ResultList {
         }
 
         let selected_index = self.state.selected().expect("Nothing selected");
+        let (file_index, _) = self.locate(selected_index);
+        let preceding_matches_count = self.match_offsets[file_index];
 
-        let mut current_file_header_index = 0;
-        for index in (0..selected_index).rev() {
-            if self.is_header(index) {
-                current_file_header_index = index;
-                break;
-            }
+        let removed_file = self.files.remove(file_index);
+        self.filtered_matches_count += removed_file.match_indices.len();
+        let line_numbers = removed_file
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                EntryType::Match(number, _, _) => Some(*number),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        self.remove_unfiltered_matches(removed_file.header(), &line_numbers);
+        self.update_offsets(file_index);
+
+        if self.files.is_empty() {
+            self.state.select(None);
+        } else {
+            // last match before the removed file, or the first one after it
+            let index = self
+                .nth_match(preceding_matches_count.saturating_sub(1))
+                .unwrap_or_else(|| self.first_match_index());
+            self.state.select(Some(index));
         }
+    }
 
-        let mut next_file_header_index = self.entries.len();
-        for index in selected_index..self.entries.len() {
-            if self.is_header(index) {
-                next_file_header_index = index;
-                break;
-            }
+    /// Keeps only matches whose line matches (`keep`) or does not match the regex.
+    pub fn refine(&mut self, matcher: RegexMatcher, keep: bool) {
+        self.fuzzy_filter("");
+
+        let mut refinement = Refinement {
+            matcher,
+            keep,
+            files: Vec::new(),
+            filtered_matches_count: self.filtered_matches_count,
+        };
+        let mut refined_files = Vec::new();
+        for file in &self.files {
+            let (refined, removed_matches_count) = refinement.apply(file);
+            refined_files.extend(refined);
+            self.filtered_matches_count += removed_matches_count;
+        }
+        // refinement might change the number of matches in a file
+        if let Some(sort_key) = self.sort_key {
+            refined_files.sort_by(|lhs, rhs| lhs.compare(rhs, sort_key));
         }
+        refinement.files = std::mem::replace(&mut self.files, refined_files);
+        self.refinements.push(refinement);
+
+        self.update_offsets(0);
+        self.select_first_match();
+    }
+
+    /// Restores results from before the last refinement.
+    pub fn undo_refinement(&mut self) {
+        self.fuzzy_filter("");
 
-        let span = next_file_header_index - current_file_header_index;
-        for _ in 0..span {
-            self.entries.remove(current_file_header_index);
+        if let Some(refinement) = self.refinements.pop() {
+            self.files = refinement.files;
+            self.filtered_matches_count = refinement.filtered_matches_count;
+
+            self.update_offsets(0);
+            self.select_first_match();
         }
+    }
+
+    /// Shows only matches fuzzy matching the query on their path and line text, best first.
+    /// Empty query restores all results in the original order.
+    pub fn fuzzy_filter(&mut self, query: &str) {
+        let files = match self.fuzzy_filter.take() {
+            Some(fuzzy_filter) => fuzzy_filter.files,
+            None if query.trim().is_empty() => return,
+            None => std::mem::take(&mut self.files),
+        };
 
-        self.filtered_matches_count += span - 1;
+        if query.trim().is_empty() {
+            self.files = files;
+        } else {
+            self.files = fuzzy_filtered(query, &files);
+            self.fuzzy_filter = Some(FuzzyFilter {
+                query: query.into(),
+                files,
+            });
+        }
 
-        if self.entries.is_empty() {
+        self.update_offsets(0);
+        self.select_first_match();
+    }
+
+    /// Sorts results by a key applied to results, keeping the selected entry selected.
+    /// Results kept for undoing refinements and clearing the fuzzy filter are sorted as well.
+    pub fn sort(&mut self, sort_key: SortKey) {
+        let selected = self.state.selected().map(|index| {
+            let (file_index, entry_index) = self.locate(index);
+            (self.files[file_index].header().to_string(), entry_index)
+        });
+
+        let compare = |lhs: &FileGroup, rhs: &FileGroup| lhs.compare(rhs, sort_key);
+        for refinement in &mut self.refinements {
+            refinement.files.sort_by(compare);
+        }
+        match &mut self.fuzzy_filter {
+            // filtered results stay ordered by their score
+            Some(fuzzy_filter) => fuzzy_filter.files.sort_by(compare),
+            None => self.files.sort_by(compare),
+        }
+        self.sort_key = Some(sort_key);
+        self.update_offsets(0);
+
+        let selected = selected.and_then(|(header, entry_index)| {
+            let file_index = self.files.iter().position(|file| file.header() == header)?;
+            Some(self.entry_offsets[file_index] + entry_index)
+        });
+        self.state.select(selected);
+    }
+
+    pub fn get_fuzzy_query(&self) -> Option<&str> {
+        self.fuzzy_filter
+            .as_ref()
+            .map(|fuzzy_filter| fuzzy_filter.query.as_str())
+    }
+
+    pub fn get_refinements_count(&self) -> usize {
+        self.refinements.len()
+    }
+
+    fn select_first_match(&mut self) {
+        if self.files.is_empty() {
             self.state.select(None);
-        } else if selected_index != 1 {
-            self.state.select(Some(cmp::max(
-                current_file_header_index.saturating_sub(1),
-                1,
-            )));
+        } else {
+            self.state.select(Some(self.first_match_index()));
         }
     }
 
-    fn is_header(&self, index: usize) -> bool {
-        matches!(self.entries[index], EntryType::Header(_))
+    /// Recomputes offsets of files starting from `first_file_index`, offsets of preceding files stay valid.
+    fn update_offsets(&mut self, first_file_index: usize) {
+        self.entry_offsets.truncate(first_file_index);
+        self.match_offsets.truncate(first_file_index);
+
+        let (mut entry_offset, mut match_offset) = match first_file_index.checked_sub(1) {
+            Some(previous) => (
+                self.entry_offsets[previous] + self.files[previous].entries.len(),
+                self.match_offsets[previous] + self.files[previous].match_indices.len(),
+            ),
+            None => (0, 0),
+        };
+        for file in &self.files[first_file_index..] {
+            self.entry_offsets.push(entry_offset);
+            self.match_offsets.push(match_offset);
+            entry_offset += file.entries.len();
+            match_offset += file.match_indices.len();
+        }
+
+        self.entries_count = entry_offset;
+        self.current_matches_count = match_offset;
+    }
+
+    /// Returns the index of the file containing the entry and the index of the entry in that file.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let file_index = self
+            .entry_offsets
+            .partition_point(|&offset| offset <= index)
+            - 1;
+        (file_index, index - self.entry_offsets[file_index])
+    }
+
+    /// Returns the index of the file containing the n-th match.
+    fn file_of_match(&self, n: usize) -> usize {
+        self.match_offsets.partition_point(|&offset| offset <= n) - 1
+    }
+
+    /// Returns the entry index of the n-th match.
+    fn nth_match(&self, n: usize) -> Option<usize> {
+        if n >= self.current_matches_count {
+            return None;
+        }
+
+        let file_index = self.file_of_match(n);
+        let match_index = self.files[file_index].match_indices[n - self.match_offsets[file_index]];
+        Some(self.entry_offsets[file_index] + match_index)
+    }
+
+    /// Returns the number of matches preceding the entry.
+    fn matches_before(&self, index: usize) -> usize {
+        if index >= self.entries_count {
+            return self.current_matches_count;
+        }
+
+        let (file_index, index_in_file) = self.locate(index);
+        self.match_offsets[file_index]
+            + self.files[file_index]
+                .match_indices
+                .partition_point(|&match_index| match_index < index_in_file)
+    }
+
+    fn first_match_index(&self) -> usize {
+        self.nth_match(0).unwrap_or(1)
     }
 
     fn is_last_match_in_file(&self) -> bool {
         let current_index = self.state.selected().expect("Nothing selected");
+        let (file_index, _) = self.locate(current_index);
 
-        self.is_header(current_index - 1)
-            && (current_index == self.entries.len() - 1 || self.is_header(current_index + 1))
+        self.files[file_index].match_indices.len() == 1
     }
 
-    fn remove_current_entry_and_select_previous(&mut self) {
+    fn remove_current_entry_and_select_next(&mut self) {
         let selected_index = self.state.selected().expect("Nothing selected");
-        self.entries.remove(selected_index);
+        let (file_index, index_in_file) = self.locate(selected_index);
+
+        let file = &mut self.files[file_index];
+        let removed_entry = file.entries.remove(index_in_file);
+        let position = file
+            .match_indices
+            .binary_search(&index_in_file)
+            .expect("Selected entry is not a match");
+        file.match_indices.remove(position);
+        for match_index in &mut file.match_indices[position..] {
+            *match_index -= 1;
+        }
         self.filtered_matches_count += 1;
 
-        if selected_index >= self.entries.len() || self.is_header(selected_index) {
-            self.state.select(Some(selected_index - 1));
+        // next match in the file, or the previous one if the last match was removed
+        let match_index = file.match_indices[position.min(file.match_indices.len() - 1)];
+        let header = file.header().to_owned();
+        if let EntryType::Match(number, _, _) = removed_entry {
+            self.remove_unfiltered_matches(&header, &[number]);
         }
+        self.update_offsets(file_index);
+        self.state
+            .select(Some(self.entry_offsets[file_index] + match_index));
     }
 
-    pub fn get_selected_entry(&self) -> Option<(String, u64)> {
-        match self.state.selected() {
-            Some(i) => {
-                let mut line_number: Option<u64> = None;
-                for index in (0..=i).rev() {
-                    match &self.entries[index] {
-                        EntryType::Header(name) => {
-                            return Some((
-                                name.to_owned(),
-                                line_number.expect("Line number not specified"),
-                            ));
-                        }
-                        EntryType::Match(number, _, _) => {
-                            if line_number.is_none() {
-                                line_number = Some(*number);
-                            }
-                        }
-                    }
+    /// Removes matches of the file removed from fuzzy filtered results also from the results
+    /// restored when the filter is cleared.
+    fn remove_unfiltered_matches(&mut self, header: &str, line_numbers: &[u64]) {
+        let Some(fuzzy_filter) = &mut self.fuzzy_filter else {
+            return;
+        };
+
+        fuzzy_filter.files = std::mem::take(&mut fuzzy_filter.files)
+            .into_iter()
+            .filter_map(|file| {
+                if file.header() != header {
+                    return Some(file);
                 }
-                None
+                remove_matches(&file, |entry| {
+                    matches!(entry, EntryType::Match(number, _, _) if line_numbers.contains(number))
+                })
+                .0
+            })
+            .collect();
+    }
+
+    pub fn get_selected_entry(&self) -> Option<(String, u64)> {
+        let (file_index, index_in_file) = self.locate(self.state.selected()?);
+        let file = &self.files[file_index];
+        match &file.entries[index_in_file] {
+            EntryType::Match(number, _, _) | EntryType::Context(number, _) => {
+                Some((file.header().to_owned(), *number))
             }
-            None => None,
+            _ => None,
         }
     }
 
     pub fn get_current_match_index(&self) -> usize {
         match self.state.selected() {
-            Some(selected) => {
-                self.entries
-                    .iter()
-                    .take(selected)
-                    .filter(|&e| matches!(e, EntryType::Match(_, _, _)))
-                    .count()
-                    + 1
-            }
+            Some(selected) => self.matches_before(selected) + 1,
             None => 0,
         }
     }
 
     pub fn get_current_number_of_matches(&self) -> usize {
-        self.entries
-            .iter()
-            .filter(|&e| matches!(e, EntryType::Match(_, _, _)))
-            .count()
+        self.current_matches_count
     }
 
     pub fn get_total_number_of_matches(&self) -> usize {
@@ -302,58 +631,169 @@ impl ResultList {
         self.filtered_matches_count
     }
 
-    pub fn draw(&mut self, frame: &mut Frame, area: Rect, theme: &dyn Theme) {
-        let files_list: Vec<ListItem> = self
-            .iter()
-            .map(|e| match e {
-                EntryType::Header(h) => {
-                    let h = h.trim_start_matches("./");
-                    ListItem::new(Span::styled(h, theme.file_path_color()))
+    pub fn draw(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+        search_config: &SearchConfig,
+        theme: &dyn Theme,
+    ) {
+        let list_widget = List::new(self.entries_count, |index| {
+            self.list_item(index, search_config, theme)
+        })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        )
+        .style(theme.background_color())
+        .highlight_style(Style::default().bg(theme.highlight_color()))
+        .scroll_offset(ScrollOffset::default().top(1).bottom(0));
+
+        let mut state = self.state;
+        frame.render_stateful_widget(list_widget, area, &mut state);
+        self.state = state;
+    }
+
+    fn list_item<'a>(
+        &'a self,
+        index: usize,
+        search_config: &SearchConfig,
+        theme: &dyn Theme,
+    ) -> ListItem<'a> {
+        let fuzzy_query = self.get_fuzzy_query();
+        let (file_index, index_in_file) = self.locate(index);
+        let file = &self.files[file_index];
+
+        match &file.entries[index_in_file] {
+            EntryType::Header(header) => {
+                let h = header.trim_start_matches("./");
+                let fuzzy_positions = fuzzy_query
+                    .and_then(|query| fuzzy::fuzzy_match(query, &[h]))
+                    .map(|(_, positions)| positions)
+                    .unwrap_or_default();
+                let mut spans =
+                    highlighted_spans(h, &[], &fuzzy_positions, theme.file_path_color(), theme);
+                // line numbers refer to the converted text, not to the file opened in the editor
+                let path = search_config.resolve_path(header);
+                if file_reader::is_preprocessed(path, search_config) {
+                    spans.push(Span::styled(
+                        " (converted by the preprocessor)",
+                        theme.context_line_color(),
+                    ));
                 }
-                EntryType::Match(n, t, offsets) => {
-                    let line_number = Span::styled(format!(" {n}: "), theme.line_number_color());
+                ListItem::new(Line::from(spans))
+            }
+            EntryType::Match(n, t, offsets) => {
+                let line_number = Span::styled(format!(" {n}: "), theme.line_number_color());
 
-                    let mut spans = vec![line_number];
+                // path and line text are matched together, only the latter is highlighted here
+                let text_start = file.header().len() + 1;
+                let fuzzy_positions = fuzzy_query
+                    .and_then(|query| fuzzy::fuzzy_match(query, &fuzzy_parts(file.header(), t)))
+                    .map(|(_, positions)| {
+                        positions
+                            .into_iter()
+                            .filter_map(|position| position.checked_sub(text_start))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
 
-                    let mut current_position = 0;
-                    for offset in offsets {
-                        let before_match =
-                            Span::styled(&t[current_position..offset.0], theme.list_font_color());
-                        let actual_match =
-                            Span::styled(&t[offset.0..offset.1], theme.match_color());
+                let mut spans = vec![line_number];
+                spans.extend(highlighted_spans(
+                    t,
+                    offsets,
+                    &fuzzy_positions,
+                    theme.list_font_color(),
+                    theme,
+                ));
 
-                        // set current position to the end of current match
-                        current_position = offset.1;
+                ListItem::new(Line::from(spans))
+            }
+            EntryType::Context(n, t) => ListItem::new(Line::from(vec![
+                Span::styled(format!(" {n}- "), theme.line_number_color()),
+                Span::styled(t.as_str(), theme.context_line_color()),
+            ])),
+            EntryType::Separator => ListItem::new(Span::styled(" --", theme.context_line_color())),
+        }
+    }
+}
 
-                        spans.push(before_match);
-                        spans.push(actual_match);
-                    }
+/// Text of a match scored by the fuzzy filter: the path and the line, matched as one text.
+fn fuzzy_parts<'a>(header: &'a str, text: &'a str) -> [&'a str; 3] {
+    [header, " ", text]
+}
 
-                    // push remaining text of a line
-                    spans.push(Span::styled(
-                        &t[current_position..],
-                        theme.list_font_color(),
-                    ));
+/// Splits text into spans styled by pattern matches at `offsets`,
+/// with characters at `fuzzy_positions` highlighted on top of them.
+fn highlighted_spans<'a>(
+    text: &'a str,
+    offsets: &[(usize, usize, usize)],
+    fuzzy_positions: &[usize],
+    default_style: Style,
+    theme: &dyn Theme,
+) -> Vec<Span<'a>> {
+    // inverted matches have no offsets, only fuzzy matched characters might be highlighted
+    let mut boundaries = vec![0, text.len()];
+    boundaries.extend(offsets.iter().flat_map(|&(start, end, _)| [start, end]));
+    boundaries.extend(fuzzy_positions.iter().flat_map(|&position| {
+        let char_len = text[position..].chars().next().map_or(0, char::len_utf8);
+        [position, position + char_len]
+    }));
+    boundaries.sort_unstable();
+    boundaries.dedup();
 
-                    ListItem::new(Line::from(spans))
-                }
-            })
-            .collect();
+    boundaries
+        .windows(2)
+        .map(|window| {
+            let (start, end) = (window[0], window[1]);
+            let style = offsets
+                .iter()
+                .find(|&&(offset_start, offset_end, _)| offset_start <= start && end <= offset_end)
+                .map_or(default_style, |&(_, _, pattern_index)| {
+                    theme.pattern_match_color(pattern_index)
+                });
+            let style = if fuzzy_positions.binary_search(&start).is_ok() {
+                style.patch(theme.fuzzy_match_style())
+            } else {
+                style
+            };
+            Span::styled(&text[start..end], style)
+        })
+        .collect()
+}
 
-        let list_widget = List::new(files_list)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_type(BorderType::Rounded),
-            )
-            .style(theme.background_color())
-            .highlight_style(Style::default().bg(theme.highlight_color()))
-            .scroll_offset(ScrollOffset::default().top(1).bottom(0));
+/// Matches accepted by the fuzzy query, ordered by their score,
+/// consecutive matches from the same file are grouped under one header.
+fn fuzzy_filtered(query: &str, files: &[FileGroup]) -> Vec<FileGroup> {
+    let mut scored = files
+        .iter()
+        .enumerate()
+        .flat_map(|(file_index, file)| {
+            file.match_indices.iter().filter_map(move |&index| {
+                let EntryType::Match(_, text, _) = &file.entries[index] else {
+                    return None;
+                };
+                fuzzy::fuzzy_score(query, &fuzzy_parts(file.header(), text))
+                    .map(|score| (score, file_index, index))
+            })
+        })
+        .collect::<Vec<_>>();
+    // stable sort keeps the original order of equally scored matches
+    scored.sort_by_key(|&(score, _, _)| std::cmp::Reverse(score));
 
-        let mut state = self.state;
-        frame.render_stateful_widget(list_widget, area, &mut state);
-        self.state = state;
+    let mut filtered: Vec<Vec<EntryType>> = Vec::new();
+    let mut last_file_index = None;
+    for (_, file_index, index) in scored {
+        if last_file_index != Some(file_index) {
+            filtered.push(vec![files[file_index].entries[0].clone()]);
+            last_file_index = Some(file_index);
+        }
+        if let Some(entries) = filtered.last_mut() {
+            entries.push(files[file_index].entries[index].clone());
+        }
     }
+    filtered.into_iter().map(FileGroup::new).collect()
 }
 
 #[cfg(test)]
@@ -379,7 +819,7 @@ mod tests {
             "entry1".into(),
             vec![GrepMatch::new(0, "e1m1".into(), vec![])],
         ));
-        assert_eq!(list.entries.len(), 2);
+        assert_eq!(list.entries_count, 2);
         assert_eq!(list.state.selected(), Some(1));
 
         list.add_entry(FileEntry::new(
@@ -389,7 +829,382 @@ mod tests {
                 GrepMatch::new(0, "e2m2".into(), vec![]),
             ],
         ));
-        assert_eq!(list.entries.len(), 5);
+        assert_eq!(list.entries_count, 5);
         assert_eq!(list.state.selected(), Some(1));
     }
+
+    fn refine_matcher(pattern: &str) -> RegexMatcher {
+        RegexMatcher::new(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_refine_keep() {
+        let mut list = ResultList::default();
+        list.add_entry(FileEntry::new(
+            "entry1".into(),
+            vec![
+                GrepMatch::new(1, "foo await".into(), vec![]),
+                GrepMatch::new(2, "foo".into(), vec![]),
+            ],
+        ));
+        list.add_entry(FileEntry::new(
+            "entry2".into(),
+            vec![GrepMatch::new(1, "foo".into(), vec![])],
+        ));
+
+        list.refine(refine_matcher("await"), true);
+        assert_eq!(list.entries_count, 2);
+        assert_eq!(list.get_filtered_matches_count(), 2);
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 1)));
+
+        list.undo_refinement();
+        assert_eq!(list.entries_count, 5);
+        assert_eq!(list.get_filtered_matches_count(), 0);
+    }
+
+    #[test]
+    fn test_refine_discard_stacks() {
+        let mut list = ResultList::default();
+        list.add_entry(FileEntry::new(
+            "entry1".into(),
+            vec![
+                GrepMatch::new(1, "foo test".into(), vec![]),
+                GrepMatch::new(2, "foo bar".into(), vec![]),
+                GrepMatch::new(3, "foo baz".into(), vec![]),
+            ],
+        ));
+
+        list.refine(refine_matcher("test"), false);
+        list.refine(refine_matcher("bar"), false);
+        assert_eq!(list.get_current_number_of_matches(), 1);
+        assert_eq!(list.get_filtered_matches_count(), 2);
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 3)));
+
+        list.undo_refinement();
+        assert_eq!(list.get_current_number_of_matches(), 2);
+        assert_eq!(list.get_filtered_matches_count(), 1);
+    }
+
+    #[test]
+    fn test_refine_new_entries() {
+        let mut list = ResultList::default();
+        list.refine(refine_matcher("await"), true);
+        list.add_entry(FileEntry::new(
+            "entry1".into(),
+            vec![
+                GrepMatch::new(1, "foo await".into(), vec![]),
+                GrepMatch::new(2, "foo".into(), vec![]),
+            ],
+        ));
+        assert_eq!(list.get_current_number_of_matches(), 1);
+        assert_eq!(list.get_filtered_matches_count(), 1);
+
+        list.undo_refinement();
+        assert_eq!(list.get_current_number_of_matches(), 2);
+        assert_eq!(list.get_filtered_matches_count(), 0);
+    }
+
+    #[test]
+    fn test_refine_removes_context_groups() {
+        let mut list = list_with_context();
+        list.refine(refine_matcher("e1m2"), true);
+
+        assert_eq!(list.entries_count, 3);
+        assert!(matches!(list.iter().nth(1), Some(EntryType::Context(9, _))));
+        assert!(matches!(
+            list.iter().nth(2),
+            Some(EntryType::Match(10, _, _))
+        ));
+    }
+
+    #[test]
+    fn test_fuzzy_filter() {
+        let mut list = ResultList::default();
+        list.add_entry(FileEntry::new(
+            "entry1".into(),
+            vec![
+                GrepMatch::new(1, "fn main".into(), vec![]),
+                GrepMatch::new(2, "let x".into(), vec![]),
+            ],
+        ));
+        list.add_entry(FileEntry::new(
+            "entry2".into(),
+            vec![
+                GrepMatch::new(1, "m a i n".into(), vec![]),
+                GrepMatch::new(2, "main".into(), vec![]),
+            ],
+        ));
+
+        list.fuzzy_filter("main");
+        assert_eq!(list.get_current_number_of_matches(), 3);
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 1)));
+        list.next_match();
+        assert_eq!(list.get_selected_entry(), Some(("entry2".into(), 2)));
+        list.next_match();
+        assert_eq!(list.get_selected_entry(), Some(("entry2".into(), 1)));
+
+        list.fuzzy_filter("entry2 main");
+        assert_eq!(list.get_current_number_of_matches(), 2);
+
+        list.fuzzy_filter("");
+        assert_eq!(list.get_fuzzy_query(), None);
+        assert_eq!(list.entries_count, 6);
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 1)));
+    }
+
+    #[test]
+    fn test_removals_are_kept_after_clearing_fuzzy_filter() {
+        let mut list = ResultList::default();
+        list.add_entry(FileEntry::new(
+            "entry1".into(),
+            vec![
+                GrepMatch::new(1, "fn main".into(), vec![]),
+                GrepMatch::new(2, "let x".into(), vec![]),
+                GrepMatch::new(3, "main()".into(), vec![]),
+            ],
+        ));
+        list.add_entry(FileEntry::new(
+            "entry2".into(),
+            vec![
+                GrepMatch::new(1, "main".into(), vec![]),
+                GrepMatch::new(2, "let y".into(), vec![]),
+            ],
+        ));
+
+        list.fuzzy_filter("main");
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 1)));
+        list.remove_current_entry();
+        list.next_match();
+        assert_eq!(list.get_selected_entry(), Some(("entry2".into(), 1)));
+        list.remove_current_file();
+        assert_eq!(list.get_current_number_of_matches(), 1);
+        assert_eq!(list.get_filtered_matches_count(), 2);
+
+        list.fuzzy_filter("");
+        assert_eq!(list.get_current_number_of_matches(), 3);
+        assert_eq!(list.get_filtered_matches_count(), 2);
+        assert_eq!(list.entries_count, 5);
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 2)));
+        list.next_match();
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 3)));
+        list.next_match();
+        assert_eq!(list.get_selected_entry(), Some(("entry2".into(), 2)));
+    }
+
+    #[test]
+    fn test_fuzzy_filter_new_entries() {
+        let mut list = ResultList::default();
+        list.fuzzy_filter("main");
+        list.add_entry(FileEntry::new(
+            "entry1".into(),
+            vec![
+                GrepMatch::new(1, "fn main".into(), vec![]),
+                GrepMatch::new(2, "let x".into(), vec![]),
+            ],
+        ));
+        assert_eq!(list.get_current_number_of_matches(), 1);
+
+        list.fuzzy_filter("");
+        assert_eq!(list.get_current_number_of_matches(), 2);
+    }
+
+    #[test]
+    fn test_fuzzy_filter_100k_matches() {
+        let mut list = ResultList::default();
+        for file in 0..1_000 {
+            list.add_entry(FileEntry::new(
+                format!("src/module_{file}/file.rs"),
+                (0..100)
+                    .map(|line| {
+                        GrepMatch::new(line, format!("let value_{line} = compute();"), vec![])
+                    })
+                    .collect(),
+            ));
+        }
+
+        let started = std::time::Instant::now();
+        list.fuzzy_filter("module_7/ value_42");
+        let elapsed = started.elapsed();
+
+        // the exact match is scored best, takes tens of milliseconds in release builds,
+        // the limit is generous to keep unoptimized builds passing
+        assert_eq!(
+            list.get_selected_entry(),
+            Some(("src/module_7/file.rs".into(), 42))
+        );
+        assert!(elapsed < std::time::Duration::from_secs(5), "{elapsed:?}");
+    }
+
+    fn list_with_context() -> ResultList {
+        let mut list = ResultList::default();
+        list.add_entry(FileEntry::new(
+            "entry1".into(),
+            vec![
+                GrepMatch::context(1, "e1c1".into()),
+                GrepMatch::new(2, "e1m1".into(), vec![]),
+                GrepMatch::context(3, "e1c2".into()),
+                GrepMatch::context(9, "e1c3".into()),
+                GrepMatch::new(10, "e1m2".into(), vec![]),
+            ],
+        ));
+        list.add_entry(FileEntry::new(
+            "entry2".into(),
+            vec![
+                GrepMatch::context(1, "e2c1".into()),
+                GrepMatch::new(2, "e2m1".into(), vec![]),
+            ],
+        ));
+        list
+    }
+
+    #[test]
+    fn test_separator_between_context_groups() {
+        let list = list_with_context();
+        assert!(matches!(list.iter().nth(4), Some(EntryType::Separator)));
+        assert_eq!(list.entries_count, 10);
+    }
+
+    #[test]
+    fn test_navigation_skips_context() {
+        let mut list = list_with_context();
+        assert_eq!(list.state.selected(), Some(2));
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 2)));
+
+        list.next_match();
+        assert_eq!(list.state.selected(), Some(6));
+        list.next_match();
+        assert_eq!(list.state.selected(), Some(9));
+        list.next_match();
+        assert_eq!(list.state.selected(), Some(9));
+        assert_eq!(list.get_current_match_index(), 3);
+
+        list.previous_file();
+        assert_eq!(list.state.selected(), Some(2));
+        list.next_file();
+        assert_eq!(list.state.selected(), Some(9));
+        list.top();
+        assert_eq!(list.state.selected(), Some(2));
+        list.bottom();
+        assert_eq!(list.state.selected(), Some(9));
+    }
+
+    #[test]
+    fn test_remove_file_with_context() {
+        let mut list = list_with_context();
+        list.remove_current_file();
+        assert_eq!(list.entries_count, 3);
+        assert_eq!(list.state.selected(), Some(2));
+        assert_eq!(list.get_filtered_matches_count(), 2);
+        assert_eq!(list.get_current_number_of_matches(), 1);
+    }
+
+    #[test]
+    fn test_remove_entry_with_context() {
+        let mut list = list_with_context();
+        list.remove_current_entry();
+        assert_eq!(list.state.selected(), Some(5));
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 10)));
+        assert_eq!(list.get_filtered_matches_count(), 1);
+    }
+
+    #[test]
+    fn test_remove_file_in_the_middle() {
+        let mut list = ResultList::default();
+        for name in ["entry1", "entry2", "entry3"] {
+            list.add_entry(FileEntry::new(
+                name.into(),
+                vec![
+                    GrepMatch::new(1, "m1".into(), vec![]),
+                    GrepMatch::new(2, "m2".into(), vec![]),
+                ],
+            ));
+        }
+
+        list.next_file();
+        list.next_match();
+        assert_eq!(list.get_current_match_index(), 4);
+        list.remove_current_file();
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 2)));
+        assert_eq!(list.get_current_match_index(), 2);
+        assert_eq!(list.get_current_number_of_matches(), 4);
+
+        list.next_match();
+        assert_eq!(list.get_selected_entry(), Some(("entry3".into(), 1)));
+        assert_eq!(list.state.selected(), Some(4));
+        list.remove_current_entry();
+        assert_eq!(list.get_selected_entry(), Some(("entry3".into(), 2)));
+        assert_eq!(list.get_current_match_index(), 3);
+        assert_eq!(list.entries_count, 5);
+    }
+
+    #[test]
+    fn test_sort_by_matches() {
+        let mut list = ResultList::new(Some(SortKey::MatchesReversed));
+        list.add_entry(FileEntry::new(
+            "entry1".into(),
+            vec![GrepMatch::new(1, "m".into(), vec![])],
+        ));
+        list.add_entry(FileEntry::new(
+            "entry2".into(),
+            vec![
+                GrepMatch::new(1, "m".into(), vec![]),
+                GrepMatch::new(2, "m".into(), vec![]),
+            ],
+        ));
+        assert_eq!(list.get_selected_entry(), Some(("entry1".into(), 1)));
+        assert_eq!(list.state.selected(), Some(4));
+
+        list.top();
+        assert_eq!(list.get_selected_entry(), Some(("entry2".into(), 1)));
+    }
+
+    #[test]
+    fn test_sort_by_depth() {
+        let mut list = ResultList::new(Some(SortKey::Depth));
+        for name in ["a/b/c", "a", "a/b"] {
+            list.add_entry(FileEntry::new(
+                name.into(),
+                vec![GrepMatch::new(1, "m".into(), vec![])],
+            ));
+        }
+
+        let headers = list
+            .iter()
+            .filter_map(|entry| match entry {
+                EntryType::Header(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(headers, ["a", "a/b", "a/b/c"]);
+    }
+
+    #[test]
+    fn test_sort_keeps_selection() {
+        let mut list = ResultList::new(None);
+        for (name, matches_count) in [("a", 1), ("b", 3), ("c", 2)] {
+            let matches = (1..=matches_count)
+                .map(|line_number| GrepMatch::new(line_number, "m".into(), vec![]))
+                .collect();
+            list.add_entry(FileEntry::new(name.into(), matches));
+        }
+        list.next_file();
+        list.next_match();
+        assert_eq!(list.get_selected_entry(), Some(("b".into(), 2)));
+
+        list.sort(SortKey::MatchesReversed);
+        assert_eq!(list.get_selected_entry(), Some(("b".into(), 2)));
+        list.top();
+        assert_eq!(list.get_selected_entry(), Some(("b".into(), 1)));
+        list.next_file();
+        assert_eq!(list.get_selected_entry(), Some(("c".into(), 1)));
+
+        list.add_entry(FileEntry::new(
+            "d".into(),
+            (1..=4)
+                .map(|line_number| GrepMatch::new(line_number, "m".into(), vec![]))
+                .collect(),
+        ));
+        list.top();
+        assert_eq!(list.get_selected_entry(), Some(("d".into(), 1)));
+    }
 }
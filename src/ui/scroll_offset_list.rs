@@ -5,7 +5,7 @@ use ratatui::{
     text::Text,
     widgets::{Block, StatefulWidget, Widget},
 };
-use std::iter::Iterator;
+use std::{collections::HashMap, iter::Iterator};
 use unicode_width::UnicodeWidthStr;
 
 #[derive(Default, Debug, Copy, Clone)]
@@ -67,10 +67,11 @@ impl ScrollOffset {
     }
 }
 
-#[derive(Debug, Clone)]
 pub struct List<'a> {
     block: Option<Block<'a>>,
-    items: Vec<ListItem<'a>>,
+    items_count: usize,
+    /// Builds the item at the given index, only items in the visible window are built
+    item: Box<dyn Fn(usize) -> ListItem<'a> + 'a>,
     /// Style used as a base style for the widget
     style: Style,
     start_corner: Corner,
@@ -82,14 +83,15 @@ pub struct List<'a> {
 }
 
 impl<'a> List<'a> {
-    pub fn new<T>(items: T) -> List<'a>
+    pub fn new<F>(items_count: usize, item: F) -> List<'a>
     where
-        T: Into<Vec<ListItem<'a>>>,
+        F: Fn(usize) -> ListItem<'a> + 'a,
     {
         List {
             block: None,
             style: Style::default(),
-            items: items.into(),
+            items_count,
+            item: Box::new(item),
             start_corner: Corner::TopLeft,
             highlight_style: Style::default(),
             highlight_symbol: None,
@@ -136,37 +138,44 @@ impl StatefulWidget for List<'_> {
             return;
         }
 
-        if self.items.is_empty() {
+        if self.items_count == 0 {
             return;
         }
         let list_height = list_area.height as usize;
+        // items measured to find the visible window are kept for rendering, each is built once
+        let mut built_items = HashMap::new();
+        let mut item_height = |index: usize| {
+            built_items
+                .entry(index)
+                .or_insert_with(|| (self.item)(index))
+                .height()
+        };
 
-        let mut start = state.offset;
-        let mut end = state.offset;
+        let mut start = state.offset.min(self.items_count);
+        let mut end = start;
         let mut height = 0;
-        for item in self.items.iter().skip(state.offset) {
-            if height + item.height() > list_height {
-                break;
-            }
-            height += item.height();
+        while end < self.items_count && height + item_height(end) <= list_height {
+            height += item_height(end);
             end += 1;
         }
 
-        let selected = state.selected.unwrap_or(0).min(self.items.len() - 1);
-        while selected >= end {
-            height = height.saturating_add(self.items[end].height());
-            end += 1;
-            while height > list_height {
-                height = height.saturating_sub(self.items[start].height());
-                start += 1;
+        // jump straight to the selected item, so that only items around it are built
+        let selected = state.selected.unwrap_or(0).min(self.items_count - 1);
+        if selected >= end {
+            start = selected;
+            end = selected + 1;
+            height = item_height(selected);
+            while start > 0 && height + item_height(start - 1) <= list_height {
+                start -= 1;
+                height += item_height(start);
             }
-        }
-        while selected < start {
-            start -= 1;
-            height = height.saturating_add(self.items[start].height());
-            while height > list_height {
-                end -= 1;
-                height = height.saturating_sub(self.items[end].height());
+        } else if selected < start {
+            start = selected;
+            end = selected;
+            height = 0;
+            while end < self.items_count && height + item_height(end) <= list_height {
+                height += item_height(end);
+                end += 1;
             }
         }
         state.offset = start;
@@ -186,13 +195,9 @@ impl StatefulWidget for List<'_> {
 
         let mut current_height = 0;
         let has_selection = state.selected.is_some();
-        for (i, item) in self
-            .items
-            .iter_mut()
-            .enumerate()
-            .skip(state.offset)
-            .take(end - start)
-        {
+        let visible_end = (state.offset + end - start).min(self.items_count);
+        for i in state.offset..visible_end {
+            let item = built_items.remove(&i).unwrap_or_else(|| (self.item)(i));
             let (x, y) = match self.start_corner {
                 Corner::BottomLeft => {
                     current_height += item.height() as u16;
@@ -242,3 +247,34 @@ impl Widget for List<'_> {
         StatefulWidget::render(self, area, buf, &mut state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[test]
+    fn builds_only_visible_items() {
+        let built_items = RefCell::new(Vec::new());
+        let list = List::new(1_000_000, |index| {
+            built_items.borrow_mut().push(index);
+            ListItem::new(index.to_string())
+        });
+
+        let area = Rect::new(0, 0, 10, 5);
+        let mut buffer = Buffer::empty(area);
+        let mut state = ListState::default();
+        state.select(Some(999_999));
+        StatefulWidget::render(list, area, &mut buffer, &mut state);
+
+        assert_eq!(state.offset, 999_995);
+        let built_items = built_items.into_inner();
+        assert!(built_items.len() < 50);
+        let mut distinct_items = built_items.clone();
+        distinct_items.sort_unstable();
+        distinct_items.dedup();
+        assert_eq!(distinct_items.len(), built_items.len(), "items built twice");
+        assert_eq!(buffer.get(0, 4).symbol(), "9");
+    }
+}
@@ -1,61 +1,179 @@
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::Stylize,
+    style::{Style, Stylize},
     text::{Line, Text},
     widgets::{Block, Borders, Clear, Paragraph},
     Frame,
 };
 
+use std::time::{Duration, Instant};
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::ig::PatternError;
+
 use super::theme::Theme;
 
 #[derive(Default)]
 pub struct SearchPopup {
     visible: bool,
-    pattern: String,
+    patterns: Vec<String>,
+    selected: usize,
     cursor_position: usize,
+    search_as_you_type: bool,
+    edited_at: Option<Instant>,
+    title: Option<&'static str>,
+    error: Option<PatternError>,
 }
 
 impl SearchPopup {
+    const DEBOUNCE_TIME: Duration = Duration::from_millis(250);
+
+    pub fn new(search_as_you_type: bool) -> Self {
+        Self {
+            search_as_you_type,
+            ..Default::default()
+        }
+    }
+
+    /// Overrides the default title describing regex patterns.
+    pub fn set_title(&mut self, title: &'static str) {
+        self.title = Some(title);
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
     pub fn toggle(&mut self) {
         self.visible = !self.visible;
+        self.edited_at = None;
+        self.error = None;
+    }
+
+    /// Shows the error below the patterns until the next search succeeds or the popup is closed.
+    pub fn set_error(&mut self, error: Option<PatternError>) {
+        self.error = error;
+    }
+
+    pub fn is_search_as_you_type(&self) -> bool {
+        self.search_as_you_type
+    }
+
+    /// Returns the patterns once they have not been edited for the debounce time.
+    pub fn take_debounced_patterns(&mut self) -> Option<Vec<String>> {
+        match self.edited_at {
+            Some(edited_at) if edited_at.elapsed() >= Self::DEBOUNCE_TIME => {
+                self.take_pending_patterns()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the patterns if they have been edited since the last search, regardless of the debounce.
+    pub fn take_pending_patterns(&mut self) -> Option<Vec<String>> {
+        self.edited_at.take().map(|_| self.get_patterns())
+    }
+
+    fn mark_edited(&mut self) {
+        if self.search_as_you_type {
+            self.edited_at = Some(Instant::now());
+        }
+    }
+
+    pub fn set_patterns(&mut self, patterns: Vec<String>) {
+        self.patterns = if patterns.is_empty() {
+            vec![String::new()]
+        } else {
+            patterns
+        };
+        self.selected = 0;
+        self.cursor_position = self.pattern().len();
+    }
+
+    /// Replaces the patterns with ones recalled from history, searching them like an edit.
+    pub fn recall_patterns(&mut self, patterns: Vec<String>) {
+        self.set_patterns(patterns);
+        self.mark_edited();
+    }
+
+    /// Returns all non-empty patterns, or a single empty one if there are none.
+    pub fn get_patterns(&self) -> Vec<String> {
+        let patterns = self
+            .patterns
+            .iter()
+            .filter(|pattern| !pattern.is_empty())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if patterns.is_empty() {
+            vec![String::new()]
+        } else {
+            patterns
+        }
     }
 
-    pub fn set_pattern(&mut self, pattern: String) {
-        self.pattern = pattern;
-        self.cursor_position = self.pattern.len();
+    fn pattern(&self) -> &String {
+        &self.patterns[self.selected]
     }
 
-    pub fn get_pattern(&self) -> String {
-        self.pattern.clone()
+    fn pattern_mut(&mut self) -> &mut String {
+        &mut self.patterns[self.selected]
     }
 
+    /// Cursor position is a byte offset, it always stays on a char boundary.
     pub fn insert_char(&mut self, c: char) {
-        self.pattern.insert(self.cursor_position, c);
+        let cursor_position = self.cursor_position;
+        self.pattern_mut().insert(cursor_position, c);
         self.move_cursor_right();
+        self.mark_edited();
     }
 
     pub fn remove_char(&mut self) {
         self.move_cursor_left();
-        if !self.pattern.is_empty() {
-            self.pattern.remove(self.cursor_position);
+        if !self.pattern().is_empty() {
+            let cursor_position = self.cursor_position;
+            self.pattern_mut().remove(cursor_position);
+            self.mark_edited();
         }
     }
 
     pub fn delete_char(&mut self) {
-        if self.cursor_position < self.pattern.len() {
-            self.pattern.remove(self.cursor_position);
+        if self.cursor_position < self.pattern().len() {
+            let cursor_position = self.cursor_position;
+            self.pattern_mut().remove(cursor_position);
+            self.mark_edited();
         }
     }
 
     pub fn move_cursor_left(&mut self) {
-        if self.cursor_position > 0 {
-            self.cursor_position -= 1;
+        if let Some(c) = self.pattern()[..self.cursor_position].chars().next_back() {
+            self.cursor_position -= c.len_utf8();
         }
     }
 
     pub fn move_cursor_right(&mut self) {
-        if self.cursor_position < self.pattern.len() {
-            self.cursor_position += 1;
+        if let Some(c) = self.pattern()[self.cursor_position..].chars().next() {
+            self.cursor_position += c.len_utf8();
+        }
+    }
+
+    /// Moves to the next pattern, adds a new one when leaving the last non-empty pattern.
+    pub fn next_pattern(&mut self) {
+        if self.selected + 1 == self.patterns.len() {
+            if self.pattern().is_empty() {
+                return;
+            }
+            self.patterns.push(String::new());
+        }
+        self.selected += 1;
+        self.cursor_position = self.pattern().len();
+    }
+
+    pub fn previous_pattern(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+            self.cursor_position = self.pattern().len();
         }
     }
 
@@ -64,13 +182,34 @@ impl SearchPopup {
             return;
         }
 
+        let title = match self.title {
+            Some(title) => title,
+            None if self.patterns.len() > 1 => " Regex Patterns ",
+            None => " Regex Pattern ",
+        };
         let block = Block::default()
             .borders(Borders::ALL)
             .border_style(theme.search_popup_border())
             .bold()
-            .title(" Regex Pattern ")
+            .title(title)
             .title_alignment(Alignment::Center);
-        let popup_area = Self::get_popup_area(frame.size(), 50);
+        let error_line = self.error.as_ref().map(|error| {
+            let pattern_str = if self.patterns.len() > 1 {
+                format!(" in pattern {}", error.pattern_index + 1)
+            } else {
+                String::default()
+            };
+            let column_str = error
+                .column
+                .map(|column| format!(" at column {column}"))
+                .unwrap_or_default();
+            Line::styled(
+                format!("Error{pattern_str}{column_str}: {}", error.message),
+                Style::default().fg(theme.invalid_input_color()),
+            )
+        });
+        let lines_count = self.patterns.len() + usize::from(error_line.is_some());
+        let popup_area = Self::get_popup_area(frame.size(), 50, lines_count as u16);
         frame.render_widget(Clear, popup_area);
 
         frame.render_widget(block, popup_area);
@@ -78,38 +217,42 @@ impl SearchPopup {
         let mut text_area = popup_area;
         text_area.y += 1; // one line below the border
         text_area.x += 2; // two chars to the right
+        text_area.height = text_area.height.saturating_sub(2);
 
         let max_text_width = text_area.width as usize - 4;
-        let pattern = if self.pattern.len() > max_text_width {
-            format!(
-                "…{}",
-                &self.pattern[self.pattern.len() - max_text_width + 1..]
-            )
-        } else {
-            self.pattern.clone()
-        };
+        let lines = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                if pattern.width() > max_text_width {
+                    Line::from(format!("…{}", tail_of_width(pattern, max_text_width - 1)))
+                } else {
+                    Line::from(pattern.as_str())
+                }
+            })
+            .chain(error_line)
+            .collect::<Vec<_>>();
 
-        let text = Text::from(Line::from(pattern.as_str()));
-        let pattern_text = Paragraph::new(text);
+        let pattern_text = Paragraph::new(Text::from(lines));
         frame.render_widget(pattern_text, text_area);
         frame.set_cursor(
             std::cmp::min(
-                text_area.x + self.cursor_position as u16,
+                text_area.x + self.pattern()[..self.cursor_position].width() as u16,
                 text_area.x + text_area.width - 4,
             ),
-            text_area.y,
+            text_area.y + self.selected as u16,
         );
     }
 
-    fn get_popup_area(frame_size: Rect, width_percent: u16) -> Rect {
-        const POPUP_HEIGHT: u16 = 3;
-        let top_bottom_margin = (frame_size.height - POPUP_HEIGHT) / 2;
+    fn get_popup_area(frame_size: Rect, width_percent: u16, lines_count: u16) -> Rect {
+        let popup_height = (lines_count + 2).min(frame_size.height);
+        let top_bottom_margin = (frame_size.height - popup_height) / 2;
         let popup_layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints(
                 [
                     Constraint::Length(top_bottom_margin),
-                    Constraint::Length(POPUP_HEIGHT),
+                    Constraint::Length(popup_height),
                     Constraint::Length(top_bottom_margin),
                 ]
                 .as_ref(),
@@ -129,3 +272,103 @@ impl SearchPopup {
             .split(popup_layout[1])[1]
     }
 }
+
+/// Returns the longest end of the text which fits in `width` columns.
+fn tail_of_width(text: &str, width: usize) -> &str {
+    let mut tail_width = 0;
+    let start = text
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| {
+            tail_width += c.width().unwrap_or_default();
+            tail_width <= width
+        })
+        .last()
+        .map_or(text.len(), |(start, _)| start);
+    &text[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn popup(search_as_you_type: bool) -> SearchPopup {
+        let mut popup = SearchPopup::new(search_as_you_type);
+        popup.set_patterns(vec![]);
+        popup
+    }
+
+    #[test]
+    fn search_as_you_type_debounces_edits() {
+        let mut popup = popup(true);
+        popup.insert_char('a');
+        assert_eq!(popup.take_debounced_patterns(), None);
+
+        std::thread::sleep(SearchPopup::DEBOUNCE_TIME);
+        assert_eq!(popup.take_debounced_patterns(), Some(vec!["a".into()]));
+        assert_eq!(popup.take_debounced_patterns(), None);
+    }
+
+    #[test]
+    fn pending_pattern_ignores_debounce() {
+        let mut popup = popup(true);
+        assert_eq!(popup.take_pending_patterns(), None);
+        popup.insert_char('a');
+        assert_eq!(popup.take_pending_patterns(), Some(vec!["a".into()]));
+        assert_eq!(popup.take_pending_patterns(), None);
+    }
+
+    #[test]
+    fn no_pending_pattern_without_search_as_you_type() {
+        let mut popup = popup(false);
+        popup.insert_char('a');
+        assert_eq!(popup.take_pending_patterns(), None);
+    }
+
+    #[test]
+    fn edit_multiple_patterns() {
+        let mut popup = popup(false);
+        popup.insert_char('a');
+        popup.next_pattern();
+        popup.next_pattern();
+        popup.insert_char('b');
+        assert_eq!(popup.patterns, ["a", "b"]);
+
+        popup.previous_pattern();
+        popup.insert_char('c');
+        popup.next_pattern();
+        popup.remove_char();
+        popup.next_pattern();
+        assert_eq!(popup.patterns, ["ac", ""]);
+        assert_eq!(popup.get_patterns(), ["ac"]);
+    }
+
+    #[test]
+    fn edit_multibyte_characters() {
+        let mut popup = popup(false);
+        for c in "zażółć".chars() {
+            popup.insert_char(c);
+        }
+        popup.move_cursor_left();
+        popup.remove_char();
+        popup.move_cursor_left();
+        popup.move_cursor_left();
+        popup.delete_char();
+        popup.move_cursor_right();
+        popup.insert_char('ę');
+        assert_eq!(popup.get_patterns(), ["zaóęć"]);
+    }
+
+    #[test]
+    fn tail_fits_width() {
+        assert_eq!(tail_of_width("zażółć", 3), "ółć");
+        assert_eq!(tail_of_width("ab", 3), "ab");
+    }
+
+    #[test]
+    fn empty_pattern_if_all_empty() {
+        let mut popup = popup(false);
+        popup.next_pattern();
+        assert_eq!(popup.get_patterns(), [""]);
+    }
+}
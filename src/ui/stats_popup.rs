@@ -0,0 +1,87 @@
+use ratatui::{
+    layout::{Alignment, Rect},
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Padding, Paragraph},
+    Frame,
+};
+
+use crate::ig::Ig;
+
+use super::{bottom_bar::format_bytes, result_list::ResultList, theme::Theme};
+
+const NAME_WIDTH: usize = 22;
+const VALUE_WIDTH: usize = 12;
+
+#[derive(Default)]
+pub struct StatsPopup {
+    visible: bool,
+}
+
+impl StatsPopup {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn draw(&self, frame: &mut Frame, ig: &Ig, result_list: &ResultList, theme: &dyn Theme) {
+        if !self.visible {
+            return;
+        }
+
+        let stats = ig.stats();
+        let rows = [
+            ("Files walked", stats.files_walked.to_string()),
+            ("Files searched", stats.files_searched.to_string()),
+            (
+                "Files with matches",
+                result_list.get_total_number_of_file_entries().to_string(),
+            ),
+            (
+                "Matches",
+                result_list.get_total_number_of_matches().to_string(),
+            ),
+            ("Bytes searched", format_bytes(stats.bytes_searched)),
+            (
+                "Binary files skipped",
+                stats.binary_files_skipped.to_string(),
+            ),
+            ("Elapsed", format!("{:.2}s", ig.elapsed().as_secs_f64())),
+        ];
+        let lines = rows
+            .iter()
+            .map(|(name, value)| Line::from(format!("{name:<NAME_WIDTH$}{value:>VALUE_WIDTH$}")))
+            .collect::<Vec<_>>();
+
+        let title = if ig.is_searching() {
+            " Search Statistics (in progress) "
+        } else {
+            " Search Statistics "
+        };
+        let popup_area = Self::get_popup_area(frame.size(), lines.len() as u16);
+        let paragraph = Paragraph::new(Text::from(lines)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.search_popup_border())
+                .title(title)
+                .title_alignment(Alignment::Center)
+                .padding(Padding::uniform(1)),
+        );
+
+        frame.render_widget(Clear, popup_area);
+        frame.render_widget(paragraph, popup_area);
+    }
+
+    fn get_popup_area(frame_size: Rect, lines_count: u16) -> Rect {
+        let height = (lines_count + 4).min(frame_size.height);
+        let y = (frame_size.height - height) / 2;
+
+        let width = ((NAME_WIDTH + VALUE_WIDTH) as u16 + 6).min(frame_size.width);
+        let x = (frame_size.width - width) / 2;
+
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
@@ -45,6 +45,31 @@ pub trait Theme {
         Style::default().fg(Color::Red)
     }
 
+    /// Color of a match of the pattern with given index when searching for multiple patterns.
+    fn pattern_match_color(&self, pattern_index: usize) -> Style {
+        const OTHER_PATTERN_COLORS: [Color; 5] = [
+            Color::Yellow,
+            Color::Cyan,
+            Color::LightBlue,
+            Color::LightRed,
+            Color::Magenta,
+        ];
+
+        match pattern_index {
+            0 => self.match_color(),
+            i => Style::default().fg(OTHER_PATTERN_COLORS[(i - 1) % OTHER_PATTERN_COLORS.len()]),
+        }
+    }
+
+    /// Applied on top of other styles to characters matched by the fuzzy filter.
+    fn fuzzy_match_style(&self) -> Style {
+        Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+    }
+
+    fn context_line_color(&self) -> Style {
+        Style::default().fg(Color::DarkGray)
+    }
+
     fn highlight_color(&self) -> Color;
 
     // Context viewer styles